@@ -1,13 +1,232 @@
 // This file contains the basic types used to communicate through the API
-use serde::{Deserialize, Serialize};
+use crate::errors::Error;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::ops::Range;
 
 /// Body of new order request
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NewOrder {
     /// Table number for the order
     pub table_number: u32,
-    /// List of strings representing the items in the order
-    pub items: Vec<String>,
+    /// Lines of the order, each one giving one or more items
+    pub items: Vec<OrderLine>,
+}
+
+impl NewOrder {
+    /// Check that the lines of the order make sense
+    ///
+    /// Fails with an InvalidField naming the faulty field otherwise, e.g. `items[1].quantity`.
+    pub fn validate(&self) -> crate::errors::Result<()> {
+        for (index, line) in self.items.iter().enumerate() {
+            if let Err((field, message)) = line.check() {
+                return Err(Error::InvalidField {
+                    field: format!("items[{}].{}", index, field),
+                    message,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Largest quantity accepted for an order line
+pub const MAX_QUANTITY: u32 = 100;
+
+/// A line of an order: an item of the menu, how many of it, and how to prepare them
+///
+/// A plain string is accepted in place of a line, as the name of a single item without note
+/// nor modifiers, so that the orders sent before lines were introduced are still valid.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OrderLine {
+    /// Name of the menu entry
+    pub name: String,
+    /// Number of items ordered, each one is prepared and tracked on its own
+    pub quantity: u32,
+    /// Free-text instructions for the kitchen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Variations of the menu entry, e.g. "no ice"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+}
+
+impl OrderLine {
+    /// Line for a single item, without note nor modifiers
+    pub fn new(name: impl Into<String>) -> Self {
+        OrderLine {
+            name: name.into(),
+            quantity: 1,
+            note: None,
+            modifiers: Vec::new(),
+        }
+    }
+
+    /// Check the quantity and modifiers, returning the faulty field and the problem otherwise
+    fn check(&self) -> Result<(), (&'static str, String)> {
+        if self.quantity == 0 || self.quantity > MAX_QUANTITY {
+            let message = format!("the quantity must be between 1 and {}", MAX_QUANTITY);
+            return Err(("quantity", message));
+        }
+        if self
+            .modifiers
+            .iter()
+            .any(|modifier| modifier.trim().is_empty())
+        {
+            return Err(("modifiers", "the modifiers can't be empty".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Order line in its full form, as an object
+#[derive(Deserialize)]
+struct FullOrderLine {
+    name: String,
+    #[serde(default = "default_quantity")]
+    quantity: u32,
+    #[serde(default)]
+    note: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<String>,
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+/// Accept either a plain name or a full line
+///
+/// Written by hand rather than with an untagged enum, so that errors in a full line still
+/// name the faulty field.
+impl<'de> Deserialize<'de> for OrderLine {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LineVisitor;
+
+        impl<'de> Visitor<'de> for LineVisitor {
+            type Value = OrderLine;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an item name or an order line")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<OrderLine, E> {
+                Ok(OrderLine::new(name))
+            }
+
+            fn visit_map<M: MapAccess<'de>>(self, map: M) -> Result<OrderLine, M::Error> {
+                let line = FullOrderLine::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(OrderLine {
+                    name: line.name,
+                    quantity: line.quantity,
+                    note: line.note,
+                    modifiers: line.modifiers,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(LineVisitor)
+    }
+}
+
+/// Compact form of an order line used on the command line
+///
+/// `<name>[*<quantity>][+<modifier>]...[#<note>]`, e.g. `Coke*2+lemon#no ice`.
+impl std::str::FromStr for OrderLine {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (line, note) = match line.split_once('#') {
+            Some((line, note)) => (line, Some(note.trim().to_string())),
+            None => (line, None),
+        };
+        let mut parts = line.split('+').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let (name, quantity) = match name.rsplit_once('*') {
+            Some((name, quantity)) => (
+                name.trim(),
+                quantity
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid quantity '{}'", quantity))?,
+            ),
+            None => (name, 1),
+        };
+        if name.is_empty() {
+            return Err(format!("Missing item name in '{}'", line));
+        }
+
+        Ok(OrderLine {
+            name: name.to_string(),
+            quantity,
+            note,
+            modifiers: parts.map(String::from).collect(),
+        })
+    }
+}
+
+/// Stage of the preparation of an item
+///
+/// Items are queued when ordered, then cooked, ready and finally served. They can be cancelled
+/// until they are served.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    #[default]
+    Queued,
+    Cooking,
+    Ready,
+    Served,
+    Cancelled,
+}
+
+impl ItemStatus {
+    /// Whether an item with this status can be given the other one
+    pub fn can_become(self, next: ItemStatus) -> bool {
+        use ItemStatus::*;
+        matches!(
+            (self, next),
+            (Queued, Cooking)
+                | (Cooking, Ready)
+                | (Ready, Served)
+                | (Queued | Cooking | Ready, Cancelled)
+        )
+    }
+
+    /// Name of the status, as used in the API
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemStatus::Queued => "queued",
+            ItemStatus::Cooking => "cooking",
+            ItemStatus::Ready => "ready",
+            ItemStatus::Served => "served",
+            ItemStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::fmt::Display for ItemStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ItemStatus {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [
+            ItemStatus::Queued,
+            ItemStatus::Cooking,
+            ItemStatus::Ready,
+            ItemStatus::Served,
+            ItemStatus::Cancelled,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == name)
+        .ok_or_else(|| format!("Unknown item status '{}'", name))
+    }
 }
 
 /// An item, as returned by the API
@@ -15,10 +234,30 @@ pub struct NewOrder {
 pub struct Item {
     /// Name given on creation
     pub name: String,
-    /// Time to completion in minutes
+    /// Minutes left before the item is ready, 0 once it is
     pub time_to_completion: u32,
+    /// When the item is ready, in seconds since the Unix epoch
+    pub ready_at: u64,
     /// Unique ID, given by the server on creation
     pub id: u32,
+    /// Stage of the preparation
+    pub status: ItemStatus,
+    /// Instructions for the kitchen given with the order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Variations of the menu entry given with the order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+    /// Price in cents, as on the menu when the item was ordered
+    #[serde(default)]
+    pub price: u64,
+}
+
+/// Body of item update requests
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateItem {
+    /// New status of the item, which must be reachable from the current one
+    pub status: ItemStatus,
 }
 
 /// A full order, as returned by the API
@@ -29,3 +268,289 @@ pub struct Order {
     /// Items in the order
     pub items: Vec<Item>,
 }
+
+/// Items of the same name and price on a bill
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BillLine {
+    /// Name of the items
+    pub name: String,
+    /// Number of items
+    pub quantity: u32,
+    /// Price of one item in cents
+    pub unit_price: u64,
+    /// Price of all the items in cents
+    pub amount: u64,
+    /// Ids of the items
+    pub items: Vec<u32>,
+}
+
+/// What a table owes, all amounts in cents
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Bill {
+    /// Table number of the order
+    pub table_number: u32,
+    /// Items billed, grouped by name and price in the order they were first ordered
+    pub lines: Vec<BillLine>,
+    /// Sum of the lines, before tax
+    pub subtotal: u64,
+    /// Tax rate in hundredths of a percent, 825 being 8.25%
+    pub tax_rate: u32,
+    /// Tax on the subtotal, rounded to the nearest cent
+    pub tax: u64,
+    /// Subtotal and tax
+    pub total: u64,
+}
+
+/// Largest number of guests a bill can be split between
+pub const MAX_GUESTS: usize = 100;
+
+/// Body of a request to split the bill of a table between guests
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitBill {
+    /// Evenly between the given number of guests
+    Even(u32),
+    /// Each guest paying for the items with the listed ids
+    ByItems(Vec<Vec<u32>>),
+}
+
+impl SplitBill {
+    /// Check that the number of guests makes sense
+    ///
+    /// Whether the items are on the bill is only known once it is computed, see
+    /// `billing::split`.
+    pub fn validate(&self) -> crate::errors::Result<()> {
+        let (field, guests) = match self {
+            SplitBill::Even(guests) => ("even", *guests as usize),
+            SplitBill::ByItems(guests) => ("by_items", guests.len()),
+        };
+        if guests == 0 || guests > MAX_GUESTS {
+            return Err(Error::InvalidField {
+                field: field.to_string(),
+                message: format!("the number of guests must be between 1 and {}", MAX_GUESTS),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// What a guest pays of a split bill, all amounts in cents
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuestShare {
+    /// Ids of the items the guest pays for, empty when the bill is split evenly
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<u32>,
+    /// Share of the subtotal
+    pub subtotal: u64,
+    /// Share of the tax
+    pub tax: u64,
+    /// Subtotal and tax
+    pub total: u64,
+}
+
+/// A bill split between guests, whose shares add up to the bill
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SharedBill {
+    /// The bill of the whole table
+    pub bill: Bill,
+    /// Share of each guest, in the order of the request
+    pub guests: Vec<GuestShare>,
+}
+
+/// Longest preparation time accepted for a menu entry, in minutes
+pub const MAX_PREP_TIME: u32 = 24 * 60;
+
+/// Highest price accepted for a menu entry, in cents
+pub const MAX_PRICE: u64 = 100_000_000;
+
+/// An entry of the menu, listing what can be ordered
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MenuEntry {
+    /// Name of the item, as given in orders
+    pub name: String,
+    /// Shortest preparation time in minutes
+    pub min_prep_time: u32,
+    /// Longest preparation time in minutes
+    pub max_prep_time: u32,
+    /// Whether the item can be ordered at the moment
+    pub available: bool,
+    /// Price of one item in cents. Entries created before prices were kept are free.
+    #[serde(default)]
+    pub price: u64,
+}
+
+impl MenuEntry {
+    /// Check that the entry makes sense
+    ///
+    /// Fails with an InvalidField naming the faulty field otherwise.
+    pub fn validate(&self) -> crate::errors::Result<()> {
+        let invalid = |field: &str, message: &str| {
+            Err(Error::InvalidField {
+                field: field.to_string(),
+                message: message.to_string(),
+            }
+            .into())
+        };
+        if self.name.trim().is_empty() {
+            return invalid("name", "the name can't be empty");
+        }
+        if self.max_prep_time > MAX_PREP_TIME {
+            return invalid(
+                "max_prep_time",
+                "the preparation can't take more than a day",
+            );
+        }
+        if self.min_prep_time > self.max_prep_time {
+            return invalid(
+                "min_prep_time",
+                "the shortest preparation time is longer than the longest one",
+            );
+        }
+        if self.price > MAX_PRICE {
+            return invalid("price", "the price can't be over a million");
+        }
+        Ok(())
+    }
+
+    /// Range of the preparation times of the item, in minutes
+    pub fn prep_times(&self) -> Range<u32> {
+        self.min_prep_time..self.max_prep_time + 1
+    }
+}
+
+/// Body of the error responses for invalid requests
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ErrorResponse {
+    /// Description of the problem
+    pub error: String,
+    /// Name of the faulty field, if the problem can be traced to one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Items of the request that caused the problem, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_status_transitions() {
+        use ItemStatus::*;
+        assert!(Queued.can_become(Cooking));
+        assert!(Cooking.can_become(Ready));
+        assert!(Ready.can_become(Served));
+        assert!(Cooking.can_become(Cancelled));
+
+        assert!(!Queued.can_become(Ready));
+        assert!(!Ready.can_become(Cooking));
+        assert!(!Served.can_become(Cancelled));
+        assert!(!Cancelled.can_become(Queued));
+        assert!(!Cooking.can_become(Cooking));
+    }
+
+    #[test]
+    fn test_validate_menu_entry() {
+        let entry = MenuEntry {
+            name: "Pizza".to_string(),
+            min_prep_time: 5,
+            max_prep_time: 5,
+            available: true,
+            price: 1200,
+        };
+        assert!(entry.validate().is_ok());
+        assert_eq!(entry.prep_times(), 5..6);
+
+        let invalid_field =
+            |entry: MenuEntry| match *entry.validate().unwrap_err().downcast::<Error>().unwrap() {
+                Error::InvalidField { field, .. } => field,
+                err => panic!("Unexpected error {:?}", err),
+            };
+        let name = MenuEntry {
+            name: " ".to_string(),
+            ..entry.clone()
+        };
+        assert_eq!(invalid_field(name), "name");
+        let range = MenuEntry {
+            min_prep_time: 6,
+            ..entry.clone()
+        };
+        assert_eq!(invalid_field(range), "min_prep_time");
+        let too_long = MenuEntry {
+            max_prep_time: MAX_PREP_TIME + 1,
+            ..entry.clone()
+        };
+        assert_eq!(invalid_field(too_long), "max_prep_time");
+        let too_expensive = MenuEntry {
+            price: MAX_PRICE + 1,
+            ..entry
+        };
+        assert_eq!(invalid_field(too_expensive), "price");
+    }
+
+    #[test]
+    fn test_order_lines() {
+        let order: NewOrder = serde_json::from_str(
+            r#"{"table_number": 1, "items": [
+                "Coke",
+                {"name": "Coke", "quantity": 2, "note": "no ice", "modifiers": ["lemon"]},
+                {"name": "Pizza"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(order.items[0], OrderLine::new("Coke"));
+        assert_eq!(
+            order.items[1],
+            OrderLine {
+                name: "Coke".to_string(),
+                quantity: 2,
+                note: Some("no ice".to_string()),
+                modifiers: vec!["lemon".to_string()],
+            }
+        );
+        assert_eq!(order.items[2], OrderLine::new("Pizza"));
+        assert!(order.validate().is_ok());
+
+        let order = NewOrder {
+            table_number: 1,
+            items: vec![
+                OrderLine::new("Coke"),
+                OrderLine {
+                    quantity: 0,
+                    ..OrderLine::new("Coke")
+                },
+            ],
+        };
+        match *order.validate().unwrap_err().downcast::<Error>().unwrap() {
+            Error::InvalidField { field, .. } => assert_eq!(field, "items[1].quantity"),
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_parse_order_line() {
+        assert_eq!("Coke".parse(), Ok(OrderLine::new("Coke")));
+        assert_eq!(
+            "Coke * 3 + lemon + straw # no ice".parse(),
+            Ok(OrderLine {
+                name: "Coke".to_string(),
+                quantity: 3,
+                note: Some("no ice".to_string()),
+                modifiers: vec!["lemon".to_string(), "straw".to_string()],
+            })
+        );
+        assert!("Coke*many".parse::<OrderLine>().is_err());
+        assert!("*2".parse::<OrderLine>().is_err());
+    }
+
+    #[test]
+    fn test_item_status_names() {
+        let status: ItemStatus = serde_json::from_str("\"cooking\"").unwrap();
+        assert_eq!(status, ItemStatus::Cooking);
+        assert_eq!("cooking".parse::<ItemStatus>(), Ok(ItemStatus::Cooking));
+        assert_eq!(serde_json::to_string(&status).unwrap(), "\"cooking\"");
+        assert!("burnt".parse::<ItemStatus>().is_err());
+    }
+}
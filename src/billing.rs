@@ -0,0 +1,399 @@
+//! Money owed by the tables
+//!
+//! Amounts are integer numbers of cents, so that sums are exact. The only rounding happens when
+//! computing the tax, which is rounded to the nearest cent (half up) once, on the subtotal.
+//! Splitting a bill between guests never rounds again, the cents left over by the division are
+//! handed out one by one so that the shares always add up to the bill.
+use crate::api::{Bill, BillLine, GuestShare, Item, ItemStatus, SharedBill, SplitBill};
+use crate::errors::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Tax rate in hundredths of a percent, e.g. 825 for 8.25%
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct TaxRate(u32);
+
+impl TaxRate {
+    /// Highest rate accepted, 100%
+    pub const MAX: TaxRate = TaxRate(10_000);
+
+    /// Rate from a number of hundredths of a percent, None if over 100%
+    pub fn from_basis_points(basis_points: u32) -> Option<Self> {
+        (basis_points <= Self::MAX.0).then_some(TaxRate(basis_points))
+    }
+
+    /// Number of hundredths of a percent
+    pub fn basis_points(self) -> u32 {
+        self.0
+    }
+
+    /// Tax on the given amount in cents, rounded to the nearest cent
+    pub fn tax_on(self, amount: u64) -> u64 {
+        let tax = (u128::from(amount) * u128::from(self.0) + 5_000) / 10_000;
+        // At most 100% of the amount, can't overflow
+        tax as u64
+    }
+}
+
+impl std::fmt::Display for TaxRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}.{:02}%", self.0 / 100, self.0 % 100)
+    }
+}
+
+/// Parse a percentage with at most two decimals, e.g. `8.25`
+impl std::str::FromStr for TaxRate {
+    type Err = String;
+
+    fn from_str(percent: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid tax rate '{}', expected a percentage like 8.25",
+                percent
+            )
+        };
+        let (units, decimals) = match percent.split_once('.') {
+            Some((_, "")) => return Err(invalid()),
+            Some(parts) => parts,
+            None => (percent, ""),
+        };
+        let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if units.is_empty() || decimals.len() > 2 || !all_digits(units) || !all_digits(decimals) {
+            return Err(invalid());
+        }
+
+        let units: u32 = units.parse().map_err(|_| invalid())?;
+        let decimals: u32 = format!("{:0<2}", decimals).parse().map_err(|_| invalid())?;
+        units
+            .checked_mul(100)
+            .and_then(|basis_points| TaxRate::from_basis_points(basis_points + decimals))
+            .ok_or_else(invalid)
+    }
+}
+
+/// Whether the item is paid for, cancelled items are not
+pub fn is_billed(item: &Item) -> bool {
+    item.status != ItemStatus::Cancelled
+}
+
+/// Bill of the given items of a table
+///
+/// Cancelled items are left out. Items with the same name and price are grouped on a single
+/// line, the lines being in the order their first item appears.
+pub fn bill(table_number: u32, items: &[Item], tax_rate: TaxRate) -> Bill {
+    let mut lines: Vec<BillLine> = Vec::new();
+    for item in items.iter().filter(|item| is_billed(item)) {
+        let same = lines
+            .iter_mut()
+            .find(|line| line.name == item.name && line.unit_price == item.price);
+        match same {
+            Some(line) => {
+                line.quantity += 1;
+                line.amount += item.price;
+                line.items.push(item.id);
+            }
+            None => lines.push(BillLine {
+                name: item.name.clone(),
+                quantity: 1,
+                unit_price: item.price,
+                amount: item.price,
+                items: vec![item.id],
+            }),
+        }
+    }
+
+    let subtotal = lines.iter().map(|line| line.amount).sum();
+    let tax = tax_rate.tax_on(subtotal);
+    Bill {
+        table_number,
+        lines,
+        subtotal,
+        tax_rate: tax_rate.basis_points(),
+        tax,
+        total: subtotal + tax,
+    }
+}
+
+/// Split an amount in parts proportional to the weights, adding up exactly to the amount
+///
+/// Each part is first rounded down, then the cents left over go one each to the parts that lost
+/// the most to the rounding, the first parts winning ties. All the parts are 0 if the weights
+/// are.
+pub fn allocate(amount: u64, weights: &[u64]) -> Vec<u64> {
+    let total_weight: u128 = weights.iter().copied().map(u128::from).sum();
+    if total_weight == 0 {
+        return vec![0; weights.len()];
+    }
+
+    let exact: Vec<u128> = weights
+        .iter()
+        .map(|weight| u128::from(amount) * u128::from(*weight))
+        .collect();
+    // No larger than the amount, since the weight is at most the total
+    let mut parts: Vec<u64> = exact
+        .iter()
+        .map(|exact| (exact / total_weight) as u64)
+        .collect();
+
+    let leftover = amount - parts.iter().sum::<u64>();
+    let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+    // Stable, so equal remainders stay in order
+    by_remainder.sort_by_key(|index| std::cmp::Reverse(exact[*index] % total_weight));
+    for index in by_remainder.into_iter().take(leftover as usize) {
+        parts[index] += 1;
+    }
+    parts
+}
+
+/// Split a bill between guests, the shares adding up exactly to the bill
+///
+/// An even split gives every guest the same total, give or take a cent, the first guests
+/// paying the extra cents. When the guests pay for their own items, the tax is shared in
+/// proportion of their subtotals. Every item of the bill must then be paid by exactly one
+/// guest, otherwise an InvalidField names the faulty guest in `by_items`.
+pub fn split(bill: Bill, how: &SplitBill) -> Result<SharedBill> {
+    let guests = match how {
+        SplitBill::Even(guests) => {
+            let evenly = vec![1; *guests as usize];
+            let subtotals = allocate(bill.subtotal, &evenly);
+            let totals = allocate(bill.total, &evenly);
+            subtotals
+                .into_iter()
+                .zip(totals)
+                .map(|(subtotal, total)| GuestShare {
+                    items: Vec::new(),
+                    subtotal,
+                    // Both amounts are rounded the same way and the total is the larger one
+                    tax: total - subtotal,
+                    total,
+                })
+                .collect()
+        }
+        SplitBill::ByItems(guests) => {
+            let subtotals = subtotals_by_guest(&bill, guests)?;
+            let taxes = allocate(bill.tax, &subtotals);
+            guests
+                .iter()
+                .zip(subtotals.into_iter().zip(taxes))
+                .map(|(items, (subtotal, tax))| GuestShare {
+                    items: items.clone(),
+                    subtotal,
+                    tax,
+                    total: subtotal + tax,
+                })
+                .collect()
+        }
+    };
+    Ok(SharedBill { bill, guests })
+}
+
+/// Sum of the prices of the items paid by each guest
+///
+/// Fails if an item is not on the bill, paid twice, or not paid at all.
+fn subtotals_by_guest(bill: &Bill, guests: &[Vec<u32>]) -> Result<Vec<u64>> {
+    let invalid =
+        |field: String, message: String| Err(Error::InvalidField { field, message }.into());
+    let mut unpaid: HashMap<u32, u64> = bill
+        .lines
+        .iter()
+        .flat_map(|line| line.items.iter().map(|id| (*id, line.unit_price)))
+        .collect();
+    let mut paid_by: HashMap<u32, usize> = HashMap::new();
+
+    let mut subtotals = Vec::with_capacity(guests.len());
+    for (guest, items) in guests.iter().enumerate() {
+        let mut subtotal = 0;
+        for id in items {
+            match (unpaid.remove(id), paid_by.get(id)) {
+                (Some(price), _) => {
+                    subtotal += price;
+                    paid_by.insert(*id, guest);
+                }
+                (None, Some(other)) => {
+                    let message = format!("item {} is already paid by guest {}", id, other);
+                    return invalid(format!("by_items[{}]", guest), message);
+                }
+                (None, None) => {
+                    let message = format!("item {} is not on the bill", id);
+                    return invalid(format!("by_items[{}]", guest), message);
+                }
+            }
+        }
+        subtotals.push(subtotal);
+    }
+
+    if !unpaid.is_empty() {
+        let mut ids: Vec<_> = unpaid.into_keys().collect();
+        ids.sort_unstable();
+        let ids: Vec<_> = ids.iter().map(u32::to_string).collect();
+        let message = format!("items {} are not paid by anyone", ids.join(", "));
+        return invalid("by_items".to_string(), message);
+    }
+    Ok(subtotals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u32, name: &str, price: u64) -> Item {
+        Item {
+            name: name.to_string(),
+            time_to_completion: 0,
+            ready_at: 0,
+            id,
+            status: ItemStatus::Served,
+            note: None,
+            modifiers: Vec::new(),
+            price,
+        }
+    }
+
+    #[test]
+    fn test_parse_tax_rate() {
+        assert_eq!("8.25".parse(), Ok(TaxRate(825)));
+        assert_eq!("10".parse(), Ok(TaxRate(1000)));
+        assert_eq!("7.5".parse(), Ok(TaxRate(750)));
+        assert_eq!("100".parse(), Ok(TaxRate::MAX));
+        for invalid in [
+            "",
+            ".5",
+            "8.",
+            "8.255",
+            "-1",
+            "100.01",
+            "ten",
+            "99999999999",
+        ] {
+            assert!(invalid.parse::<TaxRate>().is_err(), "{}", invalid);
+        }
+        assert_eq!(TaxRate(825).to_string(), "8.25%");
+    }
+
+    #[test]
+    fn test_tax_rounding() {
+        let rate = TaxRate(825);
+        assert_eq!(rate.tax_on(0), 0);
+        // Half a cent rounds up, anything less down
+        assert_eq!(rate.tax_on(6), 0);
+        assert_eq!(TaxRate(1000).tax_on(5), 1);
+        assert_eq!(TaxRate(1000).tax_on(4), 0);
+        assert_eq!(rate.tax_on(1999), 165);
+        assert_eq!(TaxRate::MAX.tax_on(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_bill() {
+        let mut cancelled = item(3, "Pizza", 1200);
+        cancelled.status = ItemStatus::Cancelled;
+        let items = [
+            item(1, "Pizza", 1200),
+            item(2, "Soda", 250),
+            cancelled,
+            item(4, "Pizza", 1200),
+            // The price changed in the meantime
+            item(5, "Soda", 300),
+        ];
+
+        let bill = bill(7, &items, TaxRate(1000));
+        assert_eq!(bill.table_number, 7);
+        let lines: Vec<_> = bill
+            .lines
+            .iter()
+            .map(|line| (line.name.as_str(), line.quantity, line.amount))
+            .collect();
+        assert_eq!(
+            lines,
+            [("Pizza", 2, 2400), ("Soda", 1, 250), ("Soda", 1, 300)]
+        );
+        assert_eq!(bill.lines[0].items, [1, 4]);
+        assert_eq!(bill.subtotal, 2950);
+        assert_eq!(bill.tax_rate, 1000);
+        assert_eq!(bill.tax, 295);
+        assert_eq!(bill.total, 3245);
+    }
+
+    #[test]
+    fn test_allocate() {
+        assert_eq!(allocate(100, &[1, 1, 1]), [34, 33, 33]);
+        assert_eq!(allocate(101, &[1, 1, 1]), [34, 34, 33]);
+        assert_eq!(allocate(2, &[1, 1, 1]), [1, 1, 0]);
+        // The largest remainders get the leftover cents, not the first parts
+        assert_eq!(allocate(10, &[1, 2, 4]), [1, 3, 6]);
+        assert_eq!(allocate(10, &[0, 0]), [0, 0]);
+        assert_eq!(allocate(0, &[3, 1]), [0, 0]);
+        assert_eq!(allocate(u64::MAX, &[u64::MAX, 1]), [u64::MAX - 1, 1]);
+
+        for amount in [0, 1, 99, 1000, 12_345] {
+            for weights in [&[1, 1, 1][..], &[5, 0, 7, 3], &[1], &[1200, 250, 250]] {
+                assert_eq!(allocate(amount, weights).iter().sum::<u64>(), amount);
+            }
+        }
+    }
+
+    fn sample_bill() -> Bill {
+        let items = [
+            item(1, "Pizza", 1200),
+            item(2, "Soda", 250),
+            item(3, "Pizza", 1200),
+            item(4, "Soda", 250),
+        ];
+        bill(7, &items, TaxRate(825))
+    }
+
+    #[test]
+    fn test_split_evenly() {
+        let shared = split(sample_bill(), &SplitBill::Even(3)).unwrap();
+        assert_eq!(shared.bill.total, 3139);
+        let totals: Vec<_> = shared.guests.iter().map(|guest| guest.total).collect();
+        assert_eq!(totals, [1047, 1046, 1046]);
+        let subtotals: Vec<_> = shared.guests.iter().map(|guest| guest.subtotal).collect();
+        assert_eq!(subtotals, [967, 967, 966]);
+        assert_eq!(
+            shared.guests.iter().map(|guest| guest.tax).sum::<u64>(),
+            shared.bill.tax
+        );
+        assert!(shared.guests.iter().all(|guest| guest.items.is_empty()));
+    }
+
+    #[test]
+    fn test_split_by_items() {
+        let guests = SplitBill::ByItems(vec![vec![1, 2], vec![3], vec![4]]);
+        let shared = split(sample_bill(), &guests).unwrap();
+        let shares: Vec<_> = shared
+            .guests
+            .iter()
+            .map(|guest| (guest.subtotal, guest.tax, guest.total))
+            .collect();
+        // 239 cents of tax, 119.2, 99 and 20.6 before rounding
+        assert_eq!(
+            shares,
+            [(1450, 119, 1569), (1200, 99, 1299), (250, 21, 271)]
+        );
+        assert_eq!(shared.guests[0].items, [1, 2]);
+        assert_eq!(
+            shared.guests.iter().map(|guest| guest.total).sum::<u64>(),
+            shared.bill.total
+        );
+
+        let invalid_field =
+            |guests: Vec<Vec<u32>>| match *split(sample_bill(), &SplitBill::ByItems(guests))
+                .unwrap_err()
+                .downcast::<Error>()
+                .unwrap()
+            {
+                Error::InvalidField { field, .. } => field,
+                err => panic!("Unexpected error {:?}", err),
+            };
+        assert_eq!(
+            invalid_field(vec![vec![1, 2, 3], vec![4, 5]]),
+            "by_items[1]"
+        );
+        assert_eq!(
+            invalid_field(vec![vec![1, 2], vec![3, 4, 2]]),
+            "by_items[1]"
+        );
+        assert_eq!(invalid_field(vec![vec![1, 2], vec![3]]), "by_items");
+    }
+}
@@ -1,9 +1,8 @@
 use common::api;
 use common::errors::Result;
-use common::http::{code_to_string, HttpClient, Response};
+use common::http::{HttpClient, Method, Response};
 use common::routes;
 use common::cli::*;
-use serde;
 
 /// Actions that may be performed by the client
 #[derive(Debug)]
@@ -11,6 +10,11 @@ enum Action {
     Get,
     Insert,
     Delete,
+    Status,
+    Menu,
+    Bill,
+    Split,
+    Checkout,
 }
 
 /// Command line options
@@ -28,6 +32,11 @@ fn parse_action(action: String) -> std::result::Result<Action, CLIError> {
         "get" => Ok(Action::Get),
         "order" => Ok(Action::Insert),
         "delete" => Ok(Action::Delete),
+        "status" => Ok(Action::Status),
+        "menu" => Ok(Action::Menu),
+        "bill" => Ok(Action::Bill),
+        "split" => Ok(Action::Split),
+        "checkout" => Ok(Action::Checkout),
         _ => Err(CLIError::InvalidParameter),
     }
 }
@@ -48,7 +57,7 @@ where
         .next()
         .ok_or(CLIError::MissingParameter("target or action"))?;
 
-    let (target, action) = match validate_address(&maybe_target.as_str()) {
+    let (target, action) = match validate_address(maybe_target.as_str()) {
         Ok(target) => (
             target,
             args.next()
@@ -89,15 +98,20 @@ fn print_response<'a, Body>(response: &'a Response)
 where
     Body: serde::Deserialize<'a> + std::fmt::Debug,
 {
-    match response.status {
-        Some(code) => println!("Response Status: {} - {}", code, code_to_string(code)),
-        None => println!("No status in response"),
-    }
+    println!(
+        "Response Status: {} - {}",
+        response.status.as_u16(),
+        response.status.reason()
+    );
     if !response.body.is_empty() {
-        let json = serde_json::from_str::<Body>(&response.body);
+        let json = serde_json::from_slice::<Body>(&response.body);
         match json {
             Ok(json) => println!("Response Body: {:?}", json),
-            Err(e) => println!("Error parsing response body: {}\n{:?}", e, response.body),
+            Err(e) => println!(
+                "Error parsing response body: {}\n{:?}",
+                e,
+                String::from_utf8_lossy(&response.body)
+            ),
         }
     }
 }
@@ -113,7 +127,7 @@ fn main() {
 
             if options.orders.is_empty() {
                 let response = client
-                    .send("GET", &routes::order_by_id(table).as_str(), "")
+                    .send(Method::Get, routes::order_by_id(table).as_str(), "")
                     .unwrap();
                 print_response::<api::Order>(&response);
                 return;
@@ -132,23 +146,29 @@ fn main() {
 
             for order in orders {
                 let response = client
-                    .send("GET", &routes::item_by_id(table, order).as_str(), "")
+                    .send(Method::Get, routes::item_by_id(table, order).as_str(), "")
                     .unwrap();
                 print_response::<api::Item>(&response);
             }
         }
         Action::Insert => {
             let table = options.table.unwrap();
+            let items = options
+                .orders
+                .iter()
+                .map(|line| line.parse::<api::OrderLine>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .unwrap();
             let body = api::NewOrder {
-                items: options.orders.clone(),
+                items,
                 table_number: table,
             };
 
             let response = client
                 .send(
-                    "POST",
+                    Method::Post,
                     routes::paths::ORDERS,
-                    &serde_json::to_string(&body).unwrap().as_str(),
+                    serde_json::to_string(&body).unwrap().as_str(),
                 )
                 .unwrap();
             print_response::<api::Order>(&response);
@@ -173,10 +193,77 @@ fn main() {
 
             for item in orders {
                 let response = client
-                    .send("DELETE", &routes::item_by_id(table, item).as_str(), "")
+                    .send(Method::Delete, routes::item_by_id(table, item).as_str(), "")
                     .unwrap();
                 print_response::<api::Item>(&response);
             }
         }
+        Action::Status => {
+            let table = options.table.unwrap();
+            let (item, status) = match options.orders.as_slice() {
+                [item, status] => (item, status),
+                _ => panic!("Expected parameters 'item id' and 'status'"),
+            };
+            let item = item.parse::<u32>().unwrap();
+            let body = api::UpdateItem {
+                status: status.parse().unwrap(),
+            };
+
+            let response = client
+                .send(
+                    Method::Patch,
+                    routes::item_by_id(table, item).as_str(),
+                    serde_json::to_string(&body).unwrap().as_str(),
+                )
+                .unwrap();
+            print_response::<api::Item>(&response);
+        }
+        Action::Menu => {
+            let response = client.send(Method::Get, routes::paths::MENU, "").unwrap();
+            print_response::<Vec<api::MenuEntry>>(&response);
+        }
+        Action::Bill => {
+            let table = options.table.unwrap();
+            let response = client
+                .send(Method::Get, routes::order_bill(table).as_str(), "")
+                .unwrap();
+            print_response::<api::Bill>(&response);
+        }
+        Action::Split => {
+            let table = options.table.unwrap();
+            let body = match options.orders.as_slice() {
+                [mode, guests] if mode == "even" => api::SplitBill::Even(guests.parse().unwrap()),
+                [mode, guests @ ..] if mode == "items" && !guests.is_empty() => {
+                    api::SplitBill::ByItems(
+                        guests
+                            .iter()
+                            .map(|items| {
+                                items
+                                    .split(',')
+                                    .map(|item| item.parse::<u32>().unwrap())
+                                    .collect()
+                            })
+                            .collect(),
+                    )
+                }
+                _ => panic!("Expected parameters 'even <guests>' or 'items <item-ids>...'"),
+            };
+
+            let response = client
+                .send(
+                    Method::Post,
+                    routes::order_split_bill(table).as_str(),
+                    serde_json::to_string(&body).unwrap().as_str(),
+                )
+                .unwrap();
+            print_response::<api::SharedBill>(&response);
+        }
+        Action::Checkout => {
+            let table = options.table.unwrap();
+            let response = client
+                .send(Method::Post, routes::order_checkout(table).as_str(), "")
+                .unwrap();
+            print_response::<api::Bill>(&response);
+        }
     }
 }
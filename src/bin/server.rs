@@ -1,43 +1,257 @@
-use common::cli;
-use common::database::{mock::MockDB, Database};
-use common::endpoints;
+use common::api::MenuEntry;
+use common::cli::{self, CLIError};
+use common::database::mock::MockDB;
+use common::database::sqlite::SqliteDB;
+use common::database::Database;
+use common::endpoints::{self, Config};
 use common::errors::*;
-use common::http::{HttpServer, Response};
-use std::sync::{Arc, Mutex};
+use common::http::HttpServer;
+use common::routes::middleware::{error_response, CatchPanic, ErrorResponses, Logger};
+use std::path::Path;
+use std::sync::Arc;
+
+/// What the server was asked to do
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// Serve HTTP requests
+    Serve,
+    /// Apply the pending migrations to the database, or just list them on a dry run
+    Migrate { dry_run: bool },
+}
+
+/// Command line options
+#[derive(Debug)]
+struct CLIOptions {
+    command: Command,
+    address: String,
+    /// Path of the SQLite database, the in-memory mock is used if missing
+    db: Option<String>,
+    /// Directory where the in-memory database is persisted, if it must survive a restart
+    journal: Option<String>,
+    /// JSON file listing menu entries to add to the database, or update, before serving
+    menu: Option<String>,
+    config: Config,
+}
+
+/// Parse the command line options
+///
+/// Either `server [<host>:<port>] [--db <path> | --journal <dir>] [--menu <file>]
+/// [--tax <percent>]` to serve requests, or
+/// `server migrate --db <path> [--dry-run]` to migrate the database without serving.
+fn parse_cli_args<I>(args: I) -> Result<CLIOptions>
+where
+    I: Iterator<Item = String>,
+{
+    let mut args = args.skip(1).peekable(); // Skip the program name
+    let mut options = CLIOptions {
+        command: Command::Serve,
+        address: cli::DEFAULT_ADDRESS.to_string(),
+        db: None,
+        journal: None,
+        menu: None,
+        config: Config::default(),
+    };
+    if args.next_if(|arg| arg == "migrate").is_some() {
+        options.command = Command::Migrate { dry_run: false };
+    }
+
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), &mut options.command) {
+            ("--db", _) => {
+                options.db = Some(args.next().ok_or(CLIError::MissingParameter("db path"))?)
+            }
+            ("--journal", Command::Serve) => {
+                options.journal = Some(
+                    args.next()
+                        .ok_or(CLIError::MissingParameter("journal directory"))?,
+                )
+            }
+            ("--menu", Command::Serve) => {
+                options.menu = Some(args.next().ok_or(CLIError::MissingParameter("menu file"))?)
+            }
+            ("--tax", Command::Serve) => {
+                let rate = args.next().ok_or(CLIError::MissingParameter("tax rate"))?;
+                options.config.tax_rate = rate.parse()?;
+            }
+            ("--dry-run", Command::Migrate { dry_run }) => *dry_run = true,
+            (_, Command::Serve) => options.address = cli::validate_address(&arg)?.to_string(),
+            _ => return Err(CLIError::InvalidParameter.into()),
+        }
+    }
+
+    if options.command != Command::Serve && options.db.is_none() {
+        return Err(CLIError::MissingParameter("db path").into());
+    }
+    if options.db.is_some() && options.journal.is_some() {
+        return Err(CLIError::InvalidParameter.into());
+    }
+    Ok(options)
+}
+
+/// Migrate the SQLite database at the given path, printing the steps
+fn migrate(path: &str, dry_run: bool) -> Result<()> {
+    let pending = SqliteDB::pending_migrations(path)?;
+    if pending.is_empty() {
+        println!("The database is up to date");
+        return Ok(());
+    }
+
+    for migration in pending {
+        println!("{}: {}", migration.version, migration.description);
+    }
+    if dry_run {
+        println!("{} pending migration(s), nothing applied", pending.len());
+    } else {
+        SqliteDB::open(path)?;
+        println!("{} migration(s) applied", pending.len());
+    }
+    Ok(())
+}
+
+/// Add the entries of the JSON menu file to the database, replacing the ones with the same
+/// name, and return their number
+///
+/// The file holds an array of entries, in the format of the `/menu` API. Nothing is added if
+/// one of them is invalid.
+fn load_menu(db: &dyn Database, path: &str) -> Result<usize> {
+    let entries: Vec<MenuEntry> = serde_json::from_slice(&std::fs::read(path)?)?;
+    for entry in &entries {
+        entry.validate()?;
+    }
+    for entry in &entries {
+        match db.add_menu_entry(entry.clone()) {
+            Err(err) if matches!(err.downcast_ref(), Some(Error::Conflict(_))) => {
+                db.update_menu_entry(entry.clone())?
+            }
+            added => added?,
+        };
+    }
+    Ok(entries.len())
+}
+
+/// Open the database selected by the options
+fn open_database(options: &CLIOptions) -> Result<Arc<dyn Database>> {
+    Ok(match (&options.db, &options.journal) {
+        (Some(path), _) => Arc::new(SqliteDB::open(path)?),
+        (None, Some(dir)) => Arc::new(MockDB::open(Path::new(dir))?),
+        (None, None) => Arc::new(MockDB::new()?),
+    })
+}
 
 fn main() {
-    let addr = std::env::args()
-        .nth(1)
-        .unwrap_or(cli::DEFAULT_ADDRESS.to_string());
-
-    let server = HttpServer::new(&addr).unwrap();
-    let router = Arc::new(endpoints::create_http_router().unwrap());
-    let db = Arc::new(Mutex::new(MockDB::new().unwrap()));
-
-    server.serve(move |request| {
-        println!("{:?}", request);
-        let result = db
-            .lock()
-            .map_err(|e| Error::InternalServerError(e.to_string()).into())
-            .and_then(|mut db| router.route(request, &mut *db));
-
-        let response = match result {
-            Ok(response) => response,
+    let options = parse_cli_args(std::env::args()).unwrap();
+    if let (Command::Migrate { dry_run }, Some(path)) = (&options.command, &options.db) {
+        if let Err(err) = migrate(path, *dry_run) {
+            eprintln!("Migration failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let server = HttpServer::new(&options.address).unwrap();
+    let mut router = endpoints::create_http_router::<dyn Database>(options.config).unwrap();
+    router.add_middleware(Logger);
+    router.add_middleware(ErrorResponses);
+    router.add_middleware(CatchPanic);
+    let router = Arc::new(router);
+    let db = open_database(&options).unwrap();
+    if let Some(path) = &options.menu {
+        match load_menu(&*db, path) {
+            Ok(count) => println!("{} menu entries loaded from {}", count, path),
             Err(err) => {
-                eprintln!("Error processing request: {:?}", &err); // can't downcast without moving
-                                                                   // apparently
-                if let Ok(err) = err.downcast::<common::errors::Error>() {
-                    match *err {
-                        Error::NotFound(_) => Response::error(404),
-                        Error::BadRequest(_) => Response::error(400),
-                        _ => Response::internal_server_error(),
-                    }
-                } else {
-                    Response::internal_server_error()
-                }
+                eprintln!("Loading the menu failed: {}", err);
+                std::process::exit(1);
             }
-        };
-        println!("{:?}", response);
-        response
-    });
+        }
+    }
+
+    server.serve(move |request| router.route(request, &*db).unwrap_or_else(error_response));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> std::vec::IntoIter<String> {
+        std::iter::once("server")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_cli_args() {
+        let options = parse_cli_args(args(&[])).unwrap();
+        assert_eq!(options.address, cli::DEFAULT_ADDRESS);
+        assert_eq!(options.db, None);
+
+        let options = parse_cli_args(args(&["--db", ":memory:", "0.0.0.0:8080"])).unwrap();
+        assert_eq!(options.address, "0.0.0.0:8080");
+        assert_eq!(options.db.as_deref(), Some(":memory:"));
+        assert!(open_database(&options).is_ok());
+
+        assert!(parse_cli_args(args(&["--db"])).is_err());
+        assert!(parse_cli_args(args(&["not an address"])).is_err());
+        assert!(parse_cli_args(args(&["--dry-run"])).is_err());
+
+        let options = parse_cli_args(args(&["--journal", "data"])).unwrap();
+        assert_eq!(options.journal.as_deref(), Some("data"));
+        assert!(parse_cli_args(args(&["--journal"])).is_err());
+        assert!(parse_cli_args(args(&["--journal", "data", "--db", "orders.db"])).is_err());
+    }
+
+    #[test]
+    fn test_load_menu() {
+        let options = parse_cli_args(args(&["--menu", "menu.json"])).unwrap();
+        assert_eq!(options.menu.as_deref(), Some("menu.json"));
+        assert!(parse_cli_args(args(&["--menu"])).is_err());
+        assert!(parse_cli_args(args(&["migrate", "--db", "orders.db", "--menu", "m"])).is_err());
+
+        // The sample menu of the repository is valid
+        let db = MockDB::new().unwrap();
+        assert!(load_menu(&db, "menu.json").unwrap() > 0);
+        assert!(db.insert_order("Pizza", 1).is_ok());
+
+        let path = std::env::temp_dir().join(format!("menu-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let pizza =
+            r#"{"name": "Pizza", "min_prep_time": 1, "max_prep_time": 1, "available": false}"#;
+        std::fs::write(path, format!("[{}]", pizza)).unwrap();
+        // Existing entries are replaced
+        assert_eq!(load_menu(&db, path).unwrap(), 1);
+        assert!(!db.get_menu_entry("Pizza").unwrap().available);
+
+        let invalid = pizza.replace("\"max_prep_time\": 1", "\"max_prep_time\": 0");
+        std::fs::write(path, format!("[{}]", invalid)).unwrap();
+        assert!(load_menu(&db, path).is_err());
+        assert!(load_menu(&db, "missing.json").is_err());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_parse_tax_rate() {
+        let options = parse_cli_args(args(&[])).unwrap();
+        assert_eq!(options.config.tax_rate.basis_points(), 0);
+
+        let options = parse_cli_args(args(&["--tax", "8.25"])).unwrap();
+        assert_eq!(options.config.tax_rate.basis_points(), 825);
+
+        assert!(parse_cli_args(args(&["--tax"])).is_err());
+        assert!(parse_cli_args(args(&["--tax", "120"])).is_err());
+        assert!(parse_cli_args(args(&["migrate", "--db", "orders.db", "--tax", "5"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_migrate_command() {
+        let options = parse_cli_args(args(&["migrate", "--db", "orders.db", "--dry-run"])).unwrap();
+        assert_eq!(options.command, Command::Migrate { dry_run: true });
+        assert_eq!(options.db.as_deref(), Some("orders.db"));
+
+        let options = parse_cli_args(args(&["migrate", "--db", "orders.db"])).unwrap();
+        assert_eq!(options.command, Command::Migrate { dry_run: false });
+
+        assert!(parse_cli_args(args(&["migrate"])).is_err());
+        assert!(parse_cli_args(args(&["migrate", "--db", "orders.db", "127.0.0.1:80"])).is_err());
+    }
 }
@@ -0,0 +1,75 @@
+//! Source of the current time
+//!
+//! The time is read through the `Clock` trait rather than directly from the system, so that
+//! the computations depending on it can be checked with a controlled time.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of seconds since the Unix epoch
+pub type Timestamp = u64;
+
+/// Gives the current time
+pub trait Clock: Send + Sync {
+    /// Current time
+    fn now(&self) -> Timestamp;
+}
+
+/// Clock following the time of the system
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        // A system clock set before 1970 is not worth an error
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+/// Clock that only moves when told to, for tests
+///
+/// Clones share the same time, so a test can keep one to move the time of a clock it gave away.
+#[derive(Debug, Default, Clone)]
+pub struct FakeClock(Arc<AtomicU64>);
+
+impl FakeClock {
+    /// Create a clock stopped at the given time
+    pub fn new(now: Timestamp) -> Self {
+        FakeClock(Arc::new(AtomicU64::new(now)))
+    }
+
+    /// Move the time forward
+    pub fn advance(&self, duration: Duration) {
+        self.0.fetch_add(duration.as_secs(), Ordering::Relaxed);
+    }
+
+    /// Set the time
+    pub fn set(&self, now: Timestamp) {
+        self.0.store(now, Ordering::Relaxed);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Timestamp {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_clock() {
+        let clock = FakeClock::new(1000);
+        let shared = clock.clone();
+        assert_eq!(shared.now(), 1000);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(shared.now(), 1090);
+        clock.set(10);
+        assert_eq!(shared.now(), 10);
+    }
+}
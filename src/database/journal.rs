@@ -0,0 +1,376 @@
+//! Append-only journal persisting the in-memory database
+//!
+//! Every change is appended to the journal and synced to disk before being applied, so an
+//! acknowledged change survives a crash. The journal is compacted from time to time by writing
+//! a snapshot of the whole database and starting over with an empty journal. On startup, the
+//! snapshot is loaded and the journal replayed on top of it.
+//!
+//! Records are JSON lines. A crash in the middle of an append can leave an incomplete last
+//! record, which is dropped when the journal is opened. An invalid record anywhere else means
+//! the file was damaged, and opening fails rather than silently losing data.
+//!
+//! Replaying a record twice has no effect, so a crash between writing a snapshot and emptying
+//! the journal only means replaying changes that are already in the snapshot.
+use super::{ArchivedItem, StoredItem};
+use crate::api::{ItemStatus, MenuEntry};
+use crate::clock::Timestamp;
+use crate::errors::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the journal in the data directory
+pub const JOURNAL_FILE: &str = "journal.log";
+
+/// Name of the snapshot in the data directory
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// A change to the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Record {
+    /// Items added to a table, with the ids they were given
+    Insert {
+        table_id: u32,
+        items: Vec<StoredItem>,
+    },
+    /// Item removed from a table
+    Delete { table_id: u32, item_id: u32 },
+    /// New status of an item
+    UpdateStatus {
+        table_id: u32,
+        item_id: u32,
+        status: ItemStatus,
+    },
+    /// Entry added to the menu, or replacing the one with the same name
+    PutMenuEntry { entry: MenuEntry },
+    /// Entry removed from the menu
+    DeleteMenuEntry { name: String },
+    /// Items of a table archived once paid
+    Checkout {
+        table_id: u32,
+        paid_at: Timestamp,
+        item_ids: Vec<u32>,
+    },
+}
+
+/// Full content of the database at some point
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Id of the next item to be inserted
+    pub next_id: u32,
+    /// Entries of the menu
+    #[serde(default)]
+    pub menu: Vec<MenuEntry>,
+    /// Items of each table, in insertion order
+    pub tables: Vec<(u32, Vec<StoredItem>)>,
+    /// Items of the tables that were checked out
+    #[serde(default)]
+    pub archive: Vec<ArchivedItem>,
+}
+
+/// Errors preventing the journal from being read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    /// A record that isn't the last one can't be read
+    Corrupted { record: usize, message: String },
+}
+
+impl std::fmt::Display for JournalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JournalError::Corrupted { record, message } => {
+                write!(f, "Journal record {} is corrupted: {}", record, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+/// Journal file open for appending
+pub struct Journal {
+    dir: PathBuf,
+    file: File,
+    /// Size of the valid records in the file
+    len: u64,
+    /// Number of records since the last snapshot
+    records: usize,
+}
+
+impl Journal {
+    /// Open the journal in the given directory, creating it if necessary
+    ///
+    /// Returns the journal along with the last snapshot and the records written since, which
+    /// must be replayed in order to rebuild the database. An incomplete last record is removed
+    /// from the file.
+    pub fn open(dir: &Path) -> Result<(Journal, Snapshot, Vec<Record>)> {
+        fs::create_dir_all(dir)?;
+        let snapshot = match fs::read(dir.join(SNAPSHOT_FILE)) {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => Snapshot::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(JOURNAL_FILE))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+
+        let (records, len) = parse_records(&content)?;
+        if len < content.len() {
+            eprintln!(
+                "Dropping {} bytes of incomplete record at the end of the journal",
+                content.len() - len
+            );
+            file.set_len(len as u64)?;
+            file.sync_all()?;
+        }
+
+        let journal = Journal {
+            dir: dir.to_path_buf(),
+            file,
+            len: len as u64,
+            records: records.len(),
+        };
+        Ok((journal, snapshot, records))
+    }
+
+    /// Number of records written since the last snapshot
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    /// Whether no record was written since the last snapshot
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    /// Append a record, returning once it is on disk
+    ///
+    /// On failure, whatever part of the record was written is removed so that the following
+    /// records can still be read.
+    pub fn append(&mut self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let written = self
+            .file
+            .write_all(&line)
+            .and_then(|_| self.file.sync_data());
+        if let Err(err) = written {
+            let _ = self.file.set_len(self.len);
+            return Err(err.into());
+        }
+
+        self.len += line.len() as u64;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Replace the snapshot with the given one and empty the journal
+    ///
+    /// The snapshot must contain all the changes recorded in the journal.
+    pub fn compact(&mut self, snapshot: &Snapshot) -> Result<()> {
+        // Write to a temporary file first, so that a crash leaves either the old snapshot or
+        // the new one
+        let temporary = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        {
+            let mut file = File::create(&temporary)?;
+            serde_json::to_writer(&mut file, snapshot)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temporary, self.dir.join(SNAPSHOT_FILE))?;
+        // Make the rename itself durable before dropping the records it replaces
+        File::open(&self.dir)?.sync_all()?;
+
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.len = 0;
+        self.records = 0;
+        Ok(())
+    }
+}
+
+/// Parse the records of a journal, returning them with the size of the valid part of the file
+///
+/// Only the last record may be invalid, it is then considered as incomplete and left out.
+fn parse_records(content: &[u8]) -> Result<(Vec<Record>, usize)> {
+    let mut records = Vec::new();
+    let mut len = 0;
+    let mut lines = content.split_inclusive(|byte| *byte == b'\n').peekable();
+
+    while let Some(line) = lines.next() {
+        let record = match line.strip_suffix(b"\n") {
+            Some(line) => serde_json::from_slice(line).map_err(|err| err.to_string()),
+            None => Err("Missing end of line".to_string()),
+        };
+        match record {
+            Ok(record) => {
+                records.push(record);
+                len += line.len();
+            }
+            Err(_) if lines.peek().is_none() => break,
+            Err(message) => {
+                return Err(JournalError::Corrupted {
+                    record: records.len() + 1,
+                    message,
+                }
+                .into())
+            }
+        }
+    }
+    Ok((records, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for the test, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn insert(table_id: u32, id: u32) -> Record {
+        Record::Insert {
+            table_id,
+            items: vec![StoredItem {
+                id,
+                name: "Pizza".to_string(),
+                created_at: 0,
+                prep_time: 5,
+                status: ItemStatus::Queued,
+                note: None,
+                modifiers: Vec::new(),
+                price: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let dir = TempDir::new("journal-replay");
+        {
+            let (mut journal, _, records) = Journal::open(&dir.0).unwrap();
+            assert!(records.is_empty());
+            journal.append(&insert(1, 0)).unwrap();
+            journal
+                .append(&Record::Delete {
+                    table_id: 1,
+                    item_id: 0,
+                })
+                .unwrap();
+            assert_eq!(journal.len(), 2);
+        }
+
+        let (journal, snapshot, records) = Journal::open(&dir.0).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert!(snapshot.tables.is_empty());
+        assert!(matches!(records[0], Record::Insert { table_id: 1, .. }));
+        assert!(matches!(
+            records[1],
+            Record::Delete {
+                table_id: 1,
+                item_id: 0
+            }
+        ));
+    }
+
+    #[test]
+    fn test_torn_record_is_truncated() {
+        let dir = TempDir::new("journal-torn");
+        {
+            let (mut journal, _, _) = Journal::open(&dir.0).unwrap();
+            journal.append(&insert(1, 0)).unwrap();
+        }
+        let path = dir.0.join(JOURNAL_FILE);
+        let valid = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"op":"insert","table_id":2,"it"#)
+            .unwrap();
+        drop(file);
+
+        let (mut journal, _, records) = Journal::open(&dir.0).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid);
+
+        // Appending after the truncation gives a readable journal
+        journal.append(&insert(2, 1)).unwrap();
+        drop(journal);
+        assert_eq!(Journal::open(&dir.0).unwrap().2.len(), 2);
+    }
+
+    #[test]
+    fn test_corrupted_record_is_refused() {
+        let dir = TempDir::new("journal-corrupted");
+        fs::create_dir_all(&dir.0).unwrap();
+        let valid = serde_json::to_string(&insert(1, 0)).unwrap();
+        fs::write(
+            dir.0.join(JOURNAL_FILE),
+            format!("{}\ngarbage\n{}\n", valid, valid),
+        )
+        .unwrap();
+
+        let err = Journal::open(&dir.0).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<JournalError>(),
+            Some(JournalError::Corrupted { record: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_records_without_timestamps() {
+        let line = br#"{"op":"insert","table_id":1,"items":[{"name":"Pizza","time_to_completion":7,"id":3}]}
+"#;
+        let (records, len) = parse_records(line).unwrap();
+        assert_eq!(len, line.len());
+        match &records[0] {
+            Record::Insert { items, .. } => {
+                assert_eq!(items[0].prep_time, 7);
+                assert_eq!(items[0].created_at, 0);
+            }
+            record => panic!("Unexpected record {:?}", record),
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let dir = TempDir::new("journal-compact");
+        {
+            let (mut journal, _, _) = Journal::open(&dir.0).unwrap();
+            journal.append(&insert(1, 0)).unwrap();
+            let snapshot = Snapshot {
+                next_id: 1,
+                menu: Vec::new(),
+                tables: vec![(1, Vec::new())],
+                archive: Vec::new(),
+            };
+            journal.compact(&snapshot).unwrap();
+            assert!(journal.is_empty());
+            journal.append(&insert(1, 1)).unwrap();
+        }
+
+        let (journal, snapshot, records) = Journal::open(&dir.0).unwrap();
+        assert_eq!(journal.len(), 1);
+        assert_eq!(snapshot.next_id, 1);
+        assert_eq!(snapshot.tables.len(), 1);
+        assert_eq!(records.len(), 1);
+    }
+}
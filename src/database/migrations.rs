@@ -0,0 +1,253 @@
+//! Versioned migrations of the SQLite schema
+//!
+//! The migrations are embedded in the binary and applied in order when a database is opened.
+//! The version of the schema is stored in the database itself (`PRAGMA user_version`), 0 being
+//! a database that was never migrated. Each migration runs in its own transaction along with
+//! the version update, so a failure leaves the database at the previous version.
+//!
+//! Migrations must never be modified once released, changes to the schema go in a new one.
+use crate::errors::Result;
+use rusqlite::Connection;
+
+/// A step in the evolution of the schema
+#[derive(Debug)]
+pub struct Migration {
+    /// Version of the schema once the migration is applied
+    pub version: u32,
+    /// Short description of the changes, for humans
+    pub description: &'static str,
+    /// Statements applying the changes
+    pub sql: &'static str,
+}
+
+/// All the migrations known to this binary, ordered by version
+///
+/// Versions start at 1 and have no gaps.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create the tables and items",
+        // IF NOT EXISTS adopts the databases created before migrations were introduced
+        sql: "
+        CREATE TABLE IF NOT EXISTS tables (
+            id INTEGER PRIMARY KEY
+        );
+        CREATE TABLE IF NOT EXISTS items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            table_id INTEGER NOT NULL REFERENCES tables(id),
+            name TEXT NOT NULL CHECK (length(name) > 0),
+            time_to_completion INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS items_by_table ON items(table_id);
+    ",
+    },
+    Migration {
+        version: 2,
+        description: "Keep the order time and total preparation time of the items",
+        // Items ordered before this migration are considered ordered at the epoch, and ready
+        sql: "
+        ALTER TABLE items RENAME COLUMN time_to_completion TO prep_time;
+        ALTER TABLE items ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+    ",
+    },
+    Migration {
+        version: 3,
+        description: "Keep the status of the items",
+        sql: "
+        ALTER TABLE items ADD COLUMN status TEXT NOT NULL DEFAULT 'queued'
+            CHECK (status IN ('queued', 'cooking', 'ready', 'served', 'cancelled'));
+    ",
+    },
+    Migration {
+        version: 4,
+        description: "Create the menu",
+        sql: "
+        CREATE TABLE menu (
+            name TEXT PRIMARY KEY CHECK (length(name) > 0),
+            min_prep_time INTEGER NOT NULL,
+            max_prep_time INTEGER NOT NULL,
+            available INTEGER NOT NULL,
+            CHECK (min_prep_time <= max_prep_time)
+        );
+    ",
+    },
+    Migration {
+        version: 5,
+        description: "Keep the note and modifiers of the items",
+        // Modifiers are a JSON array of strings
+        sql: "
+        ALTER TABLE items ADD COLUMN note TEXT;
+        ALTER TABLE items ADD COLUMN modifiers TEXT NOT NULL DEFAULT '[]';
+    ",
+    },
+    Migration {
+        version: 6,
+        description: "Keep the prices, and archive the items of the tables checked out",
+        // Entries and items created before this migration are free
+        sql: "
+        ALTER TABLE menu ADD COLUMN price INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE items ADD COLUMN price INTEGER NOT NULL DEFAULT 0;
+        CREATE TABLE archived_items (
+            id INTEGER PRIMARY KEY,
+            table_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            prep_time INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            note TEXT,
+            modifiers TEXT NOT NULL,
+            price INTEGER NOT NULL,
+            paid_at INTEGER NOT NULL
+        );
+        CREATE INDEX archived_items_by_table ON archived_items(table_id);
+    ",
+    },
+];
+
+/// Version of the schema this binary works with
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Errors preventing the migration of a database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    /// The database was migrated by a more recent binary, we don't know how to use it
+    DatabaseTooNew { database: u32, supported: u32 },
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MigrationError::DatabaseTooNew {
+                database,
+                supported,
+            } => write!(
+                f,
+                "The database schema is at version {}, but this binary only supports up to {}",
+                database, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+/// Version of the schema of the database
+pub fn current_version(connection: &Connection) -> Result<u32> {
+    Ok(connection.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Migrations that haven't been applied to the database yet, in order
+///
+/// Fails with DatabaseTooNew if the database has a version this binary doesn't know about.
+pub fn pending(connection: &Connection) -> Result<&'static [Migration]> {
+    let version = current_version(connection)?;
+    if version > latest_version() {
+        return Err(MigrationError::DatabaseTooNew {
+            database: version,
+            supported: latest_version(),
+        }
+        .into());
+    }
+    let applied = MIGRATIONS
+        .iter()
+        .take_while(|migration| migration.version <= version)
+        .count();
+    Ok(&MIGRATIONS[applied..])
+}
+
+/// Apply the pending migrations, returning the ones that were applied
+///
+/// Fails with DatabaseTooNew without touching the database if it has a version this binary
+/// doesn't know about.
+pub fn migrate(connection: &mut Connection) -> Result<&'static [Migration]> {
+    let pending = pending(connection)?;
+    for migration in pending {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration.sql)?;
+        // PRAGMA doesn't accept bound parameters, the version comes from our own constants
+        transaction.execute_batch(&format!("PRAGMA user_version = {}", migration.version))?;
+        transaction.commit()?;
+    }
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1);
+        }
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        assert_eq!(current_version(&connection).unwrap(), 0);
+        assert_eq!(pending(&connection).unwrap().len(), MIGRATIONS.len());
+
+        let applied = migrate(&mut connection).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&connection).unwrap(), latest_version());
+        assert!(pending(&connection).unwrap().is_empty());
+
+        // Nothing left to do the second time
+        assert!(migrate(&mut connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrate_keeps_items() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0].sql).unwrap();
+        connection
+            .execute_batch(
+                "PRAGMA user_version = 1;
+                 INSERT INTO tables (id) VALUES (1);
+                 INSERT INTO items (table_id, name, time_to_completion) VALUES (1, 'Pizza', 7);",
+            )
+            .unwrap();
+
+        migrate(&mut connection).unwrap();
+        let (prep_time, created_at): (u32, u64) = connection
+            .query_row("SELECT prep_time, created_at FROM items", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((prep_time, created_at), (7, 0));
+    }
+
+    #[test]
+    fn test_refuse_newer_database() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let version = latest_version() + 1;
+        connection
+            .execute_batch(&format!("PRAGMA user_version = {}", version))
+            .unwrap();
+
+        let err = migrate(&mut connection).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<MigrationError>(),
+            Some(&MigrationError::DatabaseTooNew {
+                database: version,
+                supported: latest_version()
+            })
+        );
+        assert_eq!(current_version(&connection).unwrap(), version);
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        // Conflicts with the index created by the first migration
+        connection
+            .execute_batch("CREATE TABLE items_by_table (id INTEGER);")
+            .unwrap();
+
+        assert!(migrate(&mut connection).is_err());
+        assert_eq!(current_version(&connection).unwrap(), 0);
+    }
+}
@@ -0,0 +1,1090 @@
+use crate::api::{Item, ItemStatus, MenuEntry, Order, OrderLine};
+use crate::clock::{Clock, SystemClock, Timestamp};
+use crate::errors::{Error, Result};
+use crate::random::{RandomSource, ThreadRandom};
+use serde::{Deserialize, Serialize};
+
+pub mod journal;
+pub mod migrations;
+pub mod pool;
+pub mod sqlite;
+
+/// Trait hiding the database implementation
+///
+/// I like to have at least a mock for unit tests, but I would also have a real
+/// SQL database in a real project. The trait allows to swap one for the other
+/// without touching the rest of the code.
+///
+/// The database is shared between the threads handling the requests, so all the methods take
+/// `&self`: implementations do their own synchronization, as fine-grained as they can to let
+/// unrelated requests run in parallel.
+pub trait Database: Send + Sync {
+    /// Create a new empty database
+    fn new() -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Retrieve the full order associated with the given table
+    ///
+    /// On success, return the order, on failure a database-dependent error, but should
+    /// return a NotFound error if the table is not found or has no item associated
+    fn get_order(&self, table_id: u32) -> Result<Order>;
+
+    /// Retrieve the item with the given id, associated with the given table id
+    ///
+    /// On success, return the order, on failure a database-dependent error, but should
+    /// return a NotFound error if the requests succeeds but the item is not found
+    fn get_order_item(&self, table_id: u32, order_id: u32) -> Result<Item>;
+
+
+    /// Insert a new order with a single item in the database
+    ///
+    /// On success, return the inserted item, on failure a database-dependent error
+    fn insert_order(&self, item: &str, table_id: u32) -> Result<Item>;
+
+    /// Insert a new order in the database
+    ///
+    /// Each line gives as many items as its quantity, all with the note and modifiers of the
+    /// line. The preparation time of each item is picked in the range of its menu entry. Either
+    /// all the items are inserted, or none of them.
+    ///
+    /// On success, return the inserted items, on failure a database-dependent error, but
+    /// should return an UnavailableItems error listing the items that are not on the menu or
+    /// not available.
+    fn insert_orders(&self, lines: Vec<OrderLine>, table_id: u32) -> Result<Vec<Item>>;
+
+
+    /// Delete from the database the item with the given id that is associated with the
+    /// given table id.
+    ///
+    /// On success, return the inserted items, on failure a database-dependent error, but
+    /// should return a NotFound error if the item is not found, and a Conflict error if it
+    /// was already served.
+    fn delete_item(&self, table_id: u32, order_id: u32) -> Result<Item>;
+
+    /// Change the status of the item with the given id that is associated with the given
+    /// table id
+    ///
+    /// On success, return the updated item, on failure a database-dependent error, but should
+    /// return a NotFound error if the item is not found, and a Conflict error if its status
+    /// can't become the given one (see `ItemStatus::can_become`).
+    fn update_status(&self, table_id: u32, order_id: u32, status: ItemStatus) -> Result<Item>;
+
+    /// Archive all the items of the given table once paid, so that the table starts over with
+    /// an empty order
+    ///
+    /// The items are archived whatever their status, a table may pay before everything is
+    /// served. On success, return the archived items, on failure a database-dependent error,
+    /// but should return a NotFound error if the table has no items.
+    fn checkout(&self, table_id: u32) -> Result<Vec<Item>>;
+
+    /// Retrieve all the entries of the menu, sorted by name
+    fn get_menu(&self) -> Result<Vec<MenuEntry>>;
+
+    /// Retrieve the menu entry with the given name
+    ///
+    /// Should return a NotFound error if there is none.
+    fn get_menu_entry(&self, name: &str) -> Result<MenuEntry>;
+
+    /// Add a new entry to the menu
+    ///
+    /// Should return a Conflict error if there is already an entry with the same name.
+    fn add_menu_entry(&self, entry: MenuEntry) -> Result<MenuEntry>;
+
+    /// Replace the menu entry with the same name as the given one
+    ///
+    /// Should return a NotFound error if there is none.
+    fn update_menu_entry(&self, entry: MenuEntry) -> Result<MenuEntry>;
+
+    /// Remove the menu entry with the given name, returning it
+    ///
+    /// Items already ordered are kept. Should return a NotFound error if there is no such
+    /// entry.
+    fn delete_menu_entry(&self, name: &str) -> Result<MenuEntry>;
+}
+
+/// Lines of an order repeated as many times as their quantity, one per item to insert
+fn units(lines: &[OrderLine]) -> impl Iterator<Item = &OrderLine> {
+    lines
+        .iter()
+        .flat_map(|line| std::iter::repeat_n(line, line.quantity as usize))
+}
+
+/// Preparation time and price of an item about to be inserted
+struct Pick {
+    prep_time: u32,
+    price: u64,
+}
+
+/// Pick the preparation time of the items of the given lines in the range of their menu entry,
+/// and take their price from it
+///
+/// The picks are in the same order as `units(lines)`. `find_entry` looks up the menu entry
+/// with the given name. Fails with UnavailableItems listing, once each, the items that are not
+/// on the menu or not available.
+fn pick_items<F>(
+    lines: &[OrderLine],
+    random: &dyn RandomSource,
+    mut find_entry: F,
+) -> Result<Vec<Pick>>
+where
+    F: FnMut(&str) -> Result<Option<MenuEntry>>,
+{
+    let mut picks = Vec::new();
+    let mut unavailable: Vec<String> = Vec::new();
+    for line in lines {
+        match find_entry(&line.name)? {
+            Some(entry) if entry.available => picks.extend((0..line.quantity).map(|_| Pick {
+                prep_time: random.gen_range(entry.prep_times()),
+                price: entry.price,
+            })),
+            _ if unavailable.contains(&line.name) => {}
+            _ => unavailable.push(line.name.clone()),
+        }
+    }
+
+    if unavailable.is_empty() {
+        Ok(picks)
+    } else {
+        Err(Error::UnavailableItems(unavailable).into())
+    }
+}
+
+/// Error for a missing menu entry
+fn entry_not_found(name: &str) -> Error {
+    Error::NotFound(format!("No menu entry named '{}'", name))
+}
+
+/// Error for a menu entry added twice
+fn duplicate_entry(name: &str) -> Error {
+    Error::Conflict(format!("There is already a menu entry named '{}'", name))
+}
+
+/// Error for a table without any item
+fn no_orders(table_id: u32) -> Error {
+    Error::NotFound(format!("No orders for table {}", table_id))
+}
+
+/// Add entries for the given names to the menu, all available, taking 5 to 14 minutes and
+/// costing 10.00
+#[cfg(test)]
+pub(crate) fn add_to_menu<D: Database + ?Sized>(db: &D, names: &[&str]) {
+    for name in names {
+        db.add_menu_entry(MenuEntry {
+            name: name.to_string(),
+            min_prep_time: 5,
+            max_prep_time: 14,
+            available: true,
+            price: 1000,
+        })
+        .unwrap();
+    }
+}
+
+/// An item as kept by the databases
+///
+/// The time left before the item is ready depends on when it is read, so the databases store
+/// when the item was ordered and how long it takes to prepare, and compute the API
+/// representation on the fly.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredItem {
+    /// Unique ID, given by the database on creation
+    pub id: u32,
+    /// Name given on creation
+    pub name: String,
+    /// When the item was ordered. Items recorded before timestamps were kept are considered
+    /// ordered at the epoch, and ready long ago.
+    #[serde(default)]
+    pub created_at: Timestamp,
+    /// Total preparation time in minutes
+    #[serde(alias = "time_to_completion")]
+    pub prep_time: u32,
+    /// Stage of the preparation. Items recorded before the status was kept are queued.
+    #[serde(default)]
+    pub status: ItemStatus,
+    /// Instructions for the kitchen given with the order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Variations of the menu entry given with the order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+    /// Price in cents when the item was ordered. Items recorded before prices were kept are
+    /// free.
+    #[serde(default)]
+    pub price: u64,
+}
+
+/// An item of a table that was checked out
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArchivedItem {
+    /// Table the item was ordered for
+    pub table_id: u32,
+    /// When the table was checked out
+    pub paid_at: Timestamp,
+    /// The item as it was when checked out
+    pub item: StoredItem,
+}
+
+impl StoredItem {
+    /// When the item is ready
+    pub fn ready_at(&self) -> Timestamp {
+        self.created_at + u64::from(self.prep_time) * 60
+    }
+
+    /// Representation of the item in the API at the given time
+    ///
+    /// The minutes left are rounded up, so that an item is only announced as ready (0) once it
+    /// really is.
+    pub fn to_api(&self, now: Timestamp) -> Item {
+        let ready_at = self.ready_at();
+        let remaining = ready_at.saturating_sub(now).div_ceil(60);
+        Item {
+            name: self.name.clone(),
+            time_to_completion: u32::try_from(remaining).unwrap_or(u32::MAX),
+            ready_at,
+            id: self.id,
+            status: self.status,
+            note: self.note.clone(),
+            modifiers: self.modifiers.clone(),
+            price: self.price,
+        }
+    }
+
+    /// Check that the item can be given the new status, see `ItemStatus::can_become`
+    pub fn check_transition(&self, status: ItemStatus) -> Result<()> {
+        if self.status.can_become(status) {
+            Ok(())
+        } else {
+            Err(Error::Conflict(format!(
+                "Item {} is {}, it can't become {}",
+                self.id, self.status, status
+            ))
+            .into())
+        }
+    }
+
+    /// Check that the item can be deleted, which is no longer the case once it is served
+    pub fn check_deletable(&self) -> Result<()> {
+        if self.status == ItemStatus::Served {
+            Err(Error::Conflict(format!("Item {} was served, it can't be deleted", self.id)).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_item_countdown() {
+        let item = StoredItem {
+            id: 1,
+            name: "Pizza".to_string(),
+            created_at: 1000,
+            prep_time: 10,
+            status: ItemStatus::Queued,
+            note: None,
+            modifiers: Vec::new(),
+            price: 0,
+        };
+        assert_eq!(item.ready_at(), 1600);
+
+        let at = |now| item.to_api(now).time_to_completion;
+        assert_eq!(at(1000), 10);
+        // Rounded up, the item isn't ready before the last second
+        assert_eq!(at(1001), 10);
+        assert_eq!(at(1540), 1);
+        assert_eq!(at(1599), 1);
+        assert_eq!(at(1600), 0);
+        // Never negative
+        assert_eq!(at(100_000), 0);
+        assert_eq!(item.to_api(100_000).ready_at, 1600);
+    }
+}
+
+pub mod mock {
+    use super::journal::{Journal, Record, Snapshot};
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Mutex, RwLock};
+
+    /// Items of a table, indexed by id
+    ///
+    /// Ids are assigned in increasing order as items are inserted, so iterating by id follows
+    /// the insertion order.
+    type Table = BTreeMap<u32, StoredItem>;
+
+    /// Tables of a shard, indexed by id
+    type Shard = HashMap<u32, Table>;
+
+    /// Entries of the menu, indexed by name
+    type Menu = BTreeMap<String, MenuEntry>;
+
+    /// Items of the tables that were checked out, indexed by id
+    type Archive = BTreeMap<u32, ArchivedItem>;
+
+    /// Number of independently locked parts of the mock database
+    const SHARDS: usize = 16;
+
+    /// Number of journal records after which a snapshot is taken by default
+    const SNAPSHOT_EVERY: usize = 10_000;
+
+    /// In-memory database implementation
+    ///
+    /// Items are indexed by table, then by id, so every operation is at worst logarithmic in the
+    /// number of items of the table and independent of the size of the rest of the database.
+    /// Tables without items are removed, as if they never ordered anything.
+    ///
+    /// The tables are spread over several shards, each behind its own lock, so that requests
+    /// for different tables rarely wait for each other. Ids are shared by all the shards. When
+    /// several locks are needed, they are taken in this order: menu, shards, archive, journal.
+    ///
+    /// The content is lost when the database is dropped, unless it is opened with a journal
+    /// (see `MockDB::open`).
+    pub struct MockDB {
+        menu: RwLock<Menu>,
+        shards: Vec<RwLock<Shard>>,
+        archive: Mutex<Archive>,
+        next_id: AtomicU32,
+        /// Where the changes are recorded, if they must survive a restart
+        journal: Option<Mutex<Journal>>,
+        snapshot_every: usize,
+        clock: Box<dyn Clock>,
+        random: Box<dyn RandomSource>,
+    }
+
+    impl MockDB {
+        /// Open a database persisted in the given directory, creating it if necessary
+        ///
+        /// Every change is written to a journal before being applied, and the content of the
+        /// database is rebuilt from the journal when opened again, see `journal`. The journal
+        /// is compacted into a snapshot every 10000 changes by default.
+        pub fn open(dir: &Path) -> Result<Self> {
+            let (journal, snapshot, records) = Journal::open(dir)?;
+            let db = MockDB::new()?;
+            db.next_id.store(snapshot.next_id, Ordering::Relaxed);
+            for entry in snapshot.menu {
+                db.replay(Record::PutMenuEntry { entry })?;
+            }
+            for (table_id, items) in snapshot.tables {
+                db.replay(Record::Insert { table_id, items })?;
+            }
+            db.archive.lock().map_err(poisoned)?.extend(
+                snapshot
+                    .archive
+                    .into_iter()
+                    .map(|archived| (archived.item.id, archived)),
+            );
+            for record in records {
+                db.replay(record)?;
+            }
+
+            Ok(MockDB {
+                journal: Some(Mutex::new(journal)),
+                ..db
+            })
+        }
+
+        /// Use the given clock to date the items, instead of the system clock
+        pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+            self.clock = Box::new(clock);
+            self
+        }
+
+        /// Use the given source to pick the preparation times, instead of the thread's generator
+        pub fn with_random(mut self, random: impl RandomSource + 'static) -> Self {
+            self.random = Box::new(random);
+            self
+        }
+
+        /// Set the number of journal records after which a snapshot is taken
+        pub fn snapshot_every(mut self, records: usize) -> Self {
+            self.snapshot_every = records;
+            self
+        }
+
+        /// Retrieves an item based on its name
+        ///
+        /// If several items are named the same, only the first one will be returned
+        /// Convenience function used to ease testing. Do not use in the production code
+        pub fn find_by_name(&self, name: &str) -> Option<Item> {
+            self.shards.iter().find_map(|shard| {
+                shard
+                    .read()
+                    .unwrap()
+                    .values()
+                    .flat_map(|table| table.values())
+                    .find(|item| item.name == name)
+                    .map(|item| item.to_api(self.clock.now()))
+            })
+        }
+
+        /// Retrieves the items of a table that were checked out, in insertion order
+        ///
+        /// Convenience function used to ease testing. Do not use in the production code
+        pub fn archived_items(&self, table_id: u32) -> Vec<Item> {
+            self.archive
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|archived| archived.table_id == table_id)
+                .map(|archived| archived.item.to_api(self.clock.now()))
+                .collect()
+        }
+
+        /// Shard holding the items of the given table
+        fn shard(&self, table_id: u32) -> &RwLock<Shard> {
+            &self.shards[table_id as usize % SHARDS]
+        }
+
+        /// Create a new item for an order line with a unique id
+        ///
+        /// Must be called with the lock of the shard held, so that the ids of the items of a
+        /// table are in insertion order.
+        fn new_item(&self, line: &OrderLine, pick: Pick) -> StoredItem {
+            StoredItem {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                name: line.name.clone(),
+                created_at: self.clock.now(),
+                prep_time: pick.prep_time,
+                status: ItemStatus::Queued,
+                note: line.note.clone(),
+                modifiers: line.modifiers.clone(),
+                price: pick.price,
+            }
+        }
+
+        /// Apply a change read from the journal
+        fn replay(&self, record: Record) -> Result<()> {
+            match record {
+                Record::Insert { table_id, items } => {
+                    if let Some(last) = items.iter().map(|item| item.id).max() {
+                        self.next_id.fetch_max(last + 1, Ordering::Relaxed);
+                    }
+                    let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+                    insert_items(&mut shard, table_id, items);
+                }
+                Record::Delete { table_id, item_id } => {
+                    let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+                    remove_item(&mut shard, table_id, item_id);
+                }
+                Record::UpdateStatus {
+                    table_id,
+                    item_id,
+                    status,
+                } => {
+                    let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+                    if let Some(item) = find_item(&mut shard, table_id, item_id) {
+                        item.status = status;
+                    }
+                }
+                Record::PutMenuEntry { entry } => {
+                    let mut menu = self.menu.write().map_err(poisoned)?;
+                    menu.insert(entry.name.clone(), entry);
+                }
+                Record::DeleteMenuEntry { name } => {
+                    self.menu.write().map_err(poisoned)?.remove(&name);
+                }
+                Record::Checkout {
+                    table_id,
+                    paid_at,
+                    item_ids,
+                } => {
+                    let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+                    let mut archive = self.archive.lock().map_err(poisoned)?;
+                    for item_id in item_ids {
+                        if let Some(item) = remove_item(&mut shard, table_id, item_id) {
+                            let archived = ArchivedItem {
+                                table_id,
+                                paid_at,
+                                item,
+                            };
+                            archive.insert(item_id, archived);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// Write a change to the journal, if there is one
+        ///
+        /// Must be called with the lock of the modified part held, before applying the change.
+        fn record(&self, record: &Record) -> Result<()> {
+            match &self.journal {
+                Some(journal) => journal.lock().map_err(poisoned)?.append(record),
+                None => Ok(()),
+            }
+        }
+
+        /// Replace the journal with a snapshot if it has grown too long
+        ///
+        /// Failures are only logged, the changes are safe in the journal until the next
+        /// attempt.
+        fn compact_if_needed(&self) {
+            if let Err(err) = self.compact() {
+                eprintln!("Failed to snapshot the database: {}", err);
+            }
+        }
+
+        fn compact(&self) -> Result<()> {
+            let journal = match &self.journal {
+                Some(journal) => journal,
+                None => return Ok(()),
+            };
+            // Avoid locking all the shards on every write
+            if journal.lock().map_err(poisoned)?.len() < self.snapshot_every {
+                return Ok(());
+            }
+
+            // The locks are taken in the same order as the writes do, so that nothing changes
+            // while the snapshot is taken
+            let menu = self.menu.read().map_err(poisoned)?;
+            let shards = self
+                .shards
+                .iter()
+                .map(|shard| shard.read().map_err(poisoned))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let archive = self.archive.lock().map_err(poisoned)?;
+            let mut journal = journal.lock().map_err(poisoned)?;
+            if journal.len() < self.snapshot_every {
+                // Another thread took the snapshot in the meantime
+                return Ok(());
+            }
+
+            let snapshot = Snapshot {
+                next_id: self.next_id.load(Ordering::Relaxed),
+                menu: menu.values().cloned().collect(),
+                tables: shards
+                    .iter()
+                    .flat_map(|shard| shard.iter())
+                    .map(|(table_id, table)| (*table_id, table.values().cloned().collect()))
+                    .collect(),
+                archive: archive.values().cloned().collect(),
+            };
+            journal.compact(&snapshot)
+        }
+    }
+
+    /// Add items to a table, replacing the ones with the same id
+    fn insert_items(shard: &mut Shard, table_id: u32, items: Vec<StoredItem>) {
+        if !items.is_empty() {
+            shard
+                .entry(table_id)
+                .or_default()
+                .extend(items.into_iter().map(|item| (item.id, item)));
+        }
+    }
+
+    /// Find an item of a table for modification
+    fn find_item(shard: &mut Shard, table_id: u32, item_id: u32) -> Option<&mut StoredItem> {
+        shard.get_mut(&table_id)?.get_mut(&item_id)
+    }
+
+    /// Remove an item from a table, and the table if it has no item left
+    fn remove_item(shard: &mut Shard, table_id: u32, item_id: u32) -> Option<StoredItem> {
+        let table = shard.get_mut(&table_id)?;
+        let item = table.remove(&item_id)?;
+        if table.is_empty() {
+            shard.remove(&table_id);
+        }
+        Some(item)
+    }
+
+    /// Convert a poisoned lock into an error rather than propagating the panic
+    fn poisoned<T>(err: std::sync::PoisonError<T>) -> Error {
+        Error::InternalServerError(err.to_string())
+    }
+
+    /// Error for an item missing from a table
+    fn item_not_found(table_id: u32, order_id: u32) -> Error {
+        Error::NotFound(format!(
+            "No item with id {} for table {}",
+            order_id, table_id
+        ))
+    }
+
+    impl Database for MockDB {
+        fn new() -> Result<Self> {
+            Ok(MockDB {
+                menu: RwLock::new(BTreeMap::new()),
+                shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+                archive: Mutex::new(BTreeMap::new()),
+                next_id: AtomicU32::new(0),
+                journal: None,
+                snapshot_every: SNAPSHOT_EVERY,
+                clock: Box::new(SystemClock),
+                random: Box::new(ThreadRandom),
+            })
+        }
+
+        fn insert_order(&self, item: &str, table_id: u32) -> Result<Item> {
+            let mut items = self.insert_orders(vec![OrderLine::new(item)], table_id)?;
+            Ok(items.remove(0))
+        }
+
+        fn insert_orders(&self, lines: Vec<OrderLine>, table_id: u32) -> Result<Vec<Item>> {
+            let menu = self.menu.read().map_err(poisoned)?;
+            let picks = pick_items(&lines, &*self.random, |name| Ok(menu.get(name).cloned()))?;
+
+            let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+            let items: Vec<_> = units(&lines)
+                .zip(picks)
+                .map(|(line, pick)| self.new_item(line, pick))
+                .collect();
+            self.record(&Record::Insert {
+                table_id,
+                items: items.clone(),
+            })?;
+            insert_items(&mut shard, table_id, items.clone());
+            drop(shard);
+            drop(menu);
+
+            self.compact_if_needed();
+            let now = self.clock.now();
+            Ok(items.iter().map(|item| item.to_api(now)).collect())
+        }
+
+        fn get_order(&self, table_id: u32) -> Result<Order> {
+            let now = self.clock.now();
+            let shard = self.shard(table_id).read().map_err(poisoned)?;
+            match shard.get(&table_id) {
+                Some(table) => Ok(Order {
+                    items: table.values().map(|item| item.to_api(now)).collect(),
+                    table_number: table_id,
+                }),
+                None => Err(no_orders(table_id).into()),
+            }
+        }
+
+        fn get_order_item(&self, table_id: u32, order_id: u32) -> Result<crate::api::Item> {
+            self.shard(table_id)
+                .read()
+                .map_err(poisoned)?
+                .get(&table_id)
+                .and_then(|table| table.get(&order_id))
+                .map(|item| item.to_api(self.clock.now()))
+                .ok_or_else(|| item_not_found(table_id, order_id).into())
+        }
+
+        fn delete_item(&self, table_id: u32, order_id: u32) -> Result<Item> {
+            let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+            find_item(&mut shard, table_id, order_id)
+                .ok_or_else(|| item_not_found(table_id, order_id))?
+                .check_deletable()?;
+
+            self.record(&Record::Delete {
+                table_id,
+                item_id: order_id,
+            })?;
+            let item = remove_item(&mut shard, table_id, order_id)
+                .ok_or_else(|| item_not_found(table_id, order_id))?;
+            drop(shard);
+
+            self.compact_if_needed();
+            Ok(item.to_api(self.clock.now()))
+        }
+
+        fn update_status(&self, table_id: u32, order_id: u32, status: ItemStatus) -> Result<Item> {
+            let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+            find_item(&mut shard, table_id, order_id)
+                .ok_or_else(|| item_not_found(table_id, order_id))?
+                .check_transition(status)?;
+
+            self.record(&Record::UpdateStatus {
+                table_id,
+                item_id: order_id,
+                status,
+            })?;
+            let item = find_item(&mut shard, table_id, order_id)
+                .ok_or_else(|| item_not_found(table_id, order_id))?;
+            item.status = status;
+            let item = item.to_api(self.clock.now());
+            drop(shard);
+
+            self.compact_if_needed();
+            Ok(item)
+        }
+
+        fn checkout(&self, table_id: u32) -> Result<Vec<Item>> {
+            let mut shard = self.shard(table_id).write().map_err(poisoned)?;
+            let items: Vec<_> = shard
+                .get(&table_id)
+                .map(|table| table.values().cloned().collect())
+                .unwrap_or_default();
+            if items.is_empty() {
+                return Err(no_orders(table_id).into());
+            }
+
+            let paid_at = self.clock.now();
+            let mut archive = self.archive.lock().map_err(poisoned)?;
+            self.record(&Record::Checkout {
+                table_id,
+                paid_at,
+                item_ids: items.iter().map(|item| item.id).collect(),
+            })?;
+            shard.remove(&table_id);
+            archive.extend(items.iter().map(|item| {
+                let archived = ArchivedItem {
+                    table_id,
+                    paid_at,
+                    item: item.clone(),
+                };
+                (item.id, archived)
+            }));
+            drop(archive);
+            drop(shard);
+
+            self.compact_if_needed();
+            Ok(items.iter().map(|item| item.to_api(paid_at)).collect())
+        }
+
+        fn get_menu(&self) -> Result<Vec<MenuEntry>> {
+            Ok(self
+                .menu
+                .read()
+                .map_err(poisoned)?
+                .values()
+                .cloned()
+                .collect())
+        }
+
+        fn get_menu_entry(&self, name: &str) -> Result<MenuEntry> {
+            self.menu
+                .read()
+                .map_err(poisoned)?
+                .get(name)
+                .cloned()
+                .ok_or_else(|| entry_not_found(name).into())
+        }
+
+        fn add_menu_entry(&self, entry: MenuEntry) -> Result<MenuEntry> {
+            let mut menu = self.menu.write().map_err(poisoned)?;
+            if menu.contains_key(&entry.name) {
+                return Err(duplicate_entry(&entry.name).into());
+            }
+            self.record(&Record::PutMenuEntry {
+                entry: entry.clone(),
+            })?;
+            menu.insert(entry.name.clone(), entry.clone());
+            drop(menu);
+
+            self.compact_if_needed();
+            Ok(entry)
+        }
+
+        fn update_menu_entry(&self, entry: MenuEntry) -> Result<MenuEntry> {
+            let mut menu = self.menu.write().map_err(poisoned)?;
+            if !menu.contains_key(&entry.name) {
+                return Err(entry_not_found(&entry.name).into());
+            }
+            self.record(&Record::PutMenuEntry {
+                entry: entry.clone(),
+            })?;
+            menu.insert(entry.name.clone(), entry.clone());
+            drop(menu);
+
+            self.compact_if_needed();
+            Ok(entry)
+        }
+
+        fn delete_menu_entry(&self, name: &str) -> Result<MenuEntry> {
+            let mut menu = self.menu.write().map_err(poisoned)?;
+            if !menu.contains_key(name) {
+                return Err(entry_not_found(name).into());
+            }
+            self.record(&Record::DeleteMenuEntry {
+                name: name.to_string(),
+            })?;
+            let entry = menu.remove(name).ok_or_else(|| entry_not_found(name))?;
+            drop(menu);
+
+            self.compact_if_needed();
+            Ok(entry)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::clock::FakeClock;
+        use crate::random::SeededRandom;
+        use std::time::Duration;
+
+        #[test]
+        fn test_mock_db() {
+            let db = MockDB::new().unwrap();
+            add_to_menu(&db, &["Pizza", "Burger", "Pasta"]);
+            let pizza_id = db.insert_order("Pizza", 1).unwrap().id;
+            let burger_id = db.insert_order("Burger", 2).unwrap().id;
+            let pasta_id = db.insert_order("Pasta", 1).unwrap().id;
+
+            let result = db.get_order(1).unwrap();
+            assert_eq!(result.items.len(), 2);
+            assert_eq!(result.items[0].name, "Pizza");
+            assert_eq!(result.items[0].id, pizza_id);
+            assert_eq!(result.items[1].name, "Pasta");
+            assert_eq!(result.items[1].id, pasta_id);
+
+            let result = db.get_order(2).unwrap();
+            assert_eq!(result.items.len(), 1);
+            assert_eq!(result.items[0].name, "Burger");
+            assert_eq!(result.items[0].id, burger_id);
+
+            let result = db.get_order(3);
+            assert!(result.is_err());
+
+            assert_eq!(db.get_order_item(1, pizza_id).unwrap().name, "Pizza");
+            assert_eq!(db.get_order_item(2, burger_id).unwrap().name, "Burger");
+            assert_eq!(db.get_order_item(1, pasta_id).unwrap().name, "Pasta");
+
+            assert!(db.delete_item(1, pizza_id).is_ok());
+            assert!(db.delete_item(1, pizza_id).is_err());
+            assert!(db.delete_item(1, burger_id).is_err());
+            assert!(db.delete_item(2, burger_id).is_ok());
+        }
+
+        #[test]
+        fn test_concurrent_access() {
+            let db = MockDB::new().unwrap();
+            add_to_menu(&db, &["Pizza"]);
+
+            std::thread::scope(|scope| {
+                for table in 0..8 {
+                    let db = &db;
+                    scope.spawn(move || {
+                        for _ in 0..50 {
+                            db.insert_order("Pizza", table).unwrap();
+                            db.get_order(table).unwrap();
+                        }
+                    });
+                }
+            });
+
+            let mut ids: Vec<_> = (0..8)
+                .flat_map(|table| db.get_order(table).unwrap().items)
+                .map(|item| item.id)
+                .collect();
+            assert_eq!(ids.len(), 400);
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 400);
+        }
+
+        #[test]
+        fn test_insertion_order() {
+            let db = MockDB::new().unwrap();
+            let names: Vec<_> = (0..100).map(|index| format!("Item {}", index)).collect();
+            for name in &names {
+                add_to_menu(&db, &[name]);
+            }
+            for name in &names {
+                db.insert_order(name, 1).unwrap();
+                db.insert_order(name, 2).unwrap();
+            }
+
+            let order = db.get_order(1).unwrap();
+            let removed = order.items[50].id;
+            db.delete_item(1, removed).unwrap();
+
+            let remaining: Vec<_> = db
+                .get_order(1)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|item| item.name)
+                .collect();
+            let mut expected = names.clone();
+            expected.remove(50);
+            assert_eq!(remaining, expected);
+
+            // Deleting the last item of a table removes the table
+            for item in db.get_order(2).unwrap().items {
+                db.delete_item(2, item.id).unwrap();
+            }
+            assert!(db.get_order(2).is_err());
+            assert!(db.delete_item(2, removed).is_err());
+        }
+
+        #[test]
+        fn test_countdown() {
+            let clock = FakeClock::new(1_000_000);
+            let db = MockDB::new()
+                .unwrap()
+                .with_clock(clock.clone())
+                .with_random(SeededRandom::new(1));
+            add_to_menu(&db, &["Pizza"]);
+            let prep_time = SeededRandom::new(1).gen_range(5..15);
+
+            let item = db.insert_order("Pizza", 1).unwrap();
+            assert_eq!(item.time_to_completion, prep_time);
+            assert_eq!(item.ready_at, 1_000_000 + u64::from(prep_time) * 60);
+
+            clock.advance(Duration::from_secs(90));
+            let later = db.get_order_item(1, item.id).unwrap();
+            assert_eq!(later.time_to_completion, prep_time - 1);
+            assert_eq!(later.ready_at, item.ready_at);
+
+            clock.set(item.ready_at + 3600);
+            assert_eq!(db.get_order(1).unwrap().items[0].time_to_completion, 0);
+        }
+
+        #[test]
+        fn test_order_lines() {
+            let db = MockDB::new().unwrap();
+            add_to_menu(&db, &["Coke", "Pizza"]);
+            let coke = OrderLine {
+                name: "Coke".to_string(),
+                quantity: 3,
+                note: Some("no ice".to_string()),
+                modifiers: vec!["lemon".to_string()],
+            };
+
+            let items = db
+                .insert_orders(vec![coke, OrderLine::new("Pizza")], 1)
+                .unwrap();
+            let names: Vec<_> = items.iter().map(|item| item.name.as_str()).collect();
+            assert_eq!(names, ["Coke", "Coke", "Coke", "Pizza"]);
+            assert_eq!(items[0].note.as_deref(), Some("no ice"));
+            assert_eq!(items[2].modifiers, ["lemon"]);
+            assert_eq!(items[3].note, None);
+            // Each item of a line is tracked on its own
+            db.delete_item(1, items[1].id).unwrap();
+            let item = db.get_order_item(1, items[2].id).unwrap();
+            assert_eq!(item.note.as_deref(), Some("no ice"));
+            assert_eq!(db.get_order(1).unwrap().items.len(), 3);
+        }
+
+        #[test]
+        fn test_menu() {
+            let db = MockDB::new().unwrap().with_random(SeededRandom::new(1));
+            add_to_menu(&db, &["Pizza", "Soda"]);
+            let soup = MenuEntry {
+                name: "Soup".to_string(),
+                min_prep_time: 3,
+                max_prep_time: 3,
+                available: true,
+                price: 450,
+            };
+            assert_eq!(db.add_menu_entry(soup.clone()).unwrap(), soup);
+            let err = db.add_menu_entry(soup.clone()).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::Conflict(_))
+            ));
+
+            // The preparation time is picked in the range of the entry, the price copied
+            let item = db.insert_order("Soup", 1).unwrap();
+            assert_eq!((item.time_to_completion, item.price), (3, 450));
+
+            let soup = MenuEntry {
+                available: false,
+                ..soup
+            };
+            db.update_menu_entry(soup.clone()).unwrap();
+            assert_eq!(db.get_menu_entry("Soup").unwrap(), soup);
+            db.delete_menu_entry("Soda").unwrap();
+            assert!(db.get_menu_entry("Soda").is_err());
+            assert!(db.delete_menu_entry("Soda").is_err());
+            assert_eq!(db.get_menu().unwrap().len(), 2);
+
+            // Nothing is inserted if an item can't be ordered, and each is listed once
+            let items = ["Pizza", "Soup", "Soda", "Soup"]
+                .map(OrderLine::new)
+                .to_vec();
+            let err = db.insert_orders(items, 2).unwrap_err();
+            match err.downcast_ref::<Error>() {
+                Some(Error::UnavailableItems(names)) => assert_eq!(names, &["Soup", "Soda"]),
+                err => panic!("Unexpected error {:?}", err),
+            }
+            assert!(db.get_order(2).is_err());
+            // Items already ordered are kept
+            assert_eq!(db.get_order(1).unwrap().items.len(), 1);
+        }
+
+        #[test]
+        fn test_checkout() {
+            let db = MockDB::new().unwrap();
+            add_to_menu(&db, &["Pizza", "Soda"]);
+            let items = db
+                .insert_orders(vec![OrderLine::new("Pizza"), OrderLine::new("Soda")], 1)
+                .unwrap();
+            db.insert_order("Pizza", 2).unwrap();
+
+            for status in [ItemStatus::Cooking, ItemStatus::Ready, ItemStatus::Served] {
+                db.update_status(1, items[0].id, status).unwrap();
+            }
+
+            // Items not served yet are paid for all the same
+            let paid = db.checkout(1).unwrap();
+            assert_eq!(paid.len(), 2);
+            assert_eq!(paid[0].price, 1000);
+            assert_eq!(paid[1].status, ItemStatus::Queued);
+            assert!(db.get_order(1).is_err());
+            assert_eq!(db.archived_items(1).len(), 2);
+            let err = db.checkout(1).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::NotFound(_))
+            ));
+            // Other tables are left alone
+            assert_eq!(db.get_order(2).unwrap().items.len(), 1);
+            assert!(db.archived_items(2).is_empty());
+        }
+
+        #[test]
+        fn test_journal() {
+            let dir = std::env::temp_dir().join(format!("orders-{}-journal", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+
+            let (pizza, soda) = {
+                let db = MockDB::open(&dir).unwrap().snapshot_every(3);
+                add_to_menu(&db, &["Pizza", "Burger", "Soda", "Pasta"]);
+                let pasta = db.insert_order("Pasta", 3).unwrap();
+                db.update_status(3, pasta.id, ItemStatus::Cancelled)
+                    .unwrap();
+                db.checkout(3).unwrap();
+                let pizza = OrderLine {
+                    note: Some("well done".to_string()),
+                    ..OrderLine::new("Pizza")
+                };
+                let pizza = db.insert_orders(vec![pizza], 1).unwrap().remove(0);
+                let burger = db.insert_order("Burger", 1).unwrap();
+                let soda = db.insert_order("Soda", 2).unwrap();
+                // Compacted into a snapshot, then recorded in the new journal
+                db.delete_item(1, burger.id).unwrap();
+                db.update_status(2, soda.id, ItemStatus::Cooking).unwrap();
+                db.delete_menu_entry("Burger").unwrap();
+                (pizza, soda)
+            };
+            assert!(dir.join(journal::SNAPSHOT_FILE).exists());
+
+            let db = MockDB::open(&dir).unwrap();
+            let order = db.get_order(1).unwrap();
+            assert_eq!(order.items.len(), 1);
+            assert_eq!(order.items[0].id, pizza.id);
+            assert_eq!(order.items[0].note.as_deref(), Some("well done"));
+            let item = db.get_order_item(2, soda.id).unwrap();
+            assert_eq!(item.name, "Soda");
+            assert_eq!(item.status, ItemStatus::Cooking);
+            let menu: Vec<_> = db
+                .get_menu()
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect();
+            assert_eq!(menu, ["Pasta", "Pizza", "Soda"]);
+            assert!(db.get_order(3).is_err());
+            assert_eq!(db.archived_items(3)[0].name, "Pasta");
+            // Ids are not reused after a restart
+            assert!(db.insert_order("Pasta", 2).unwrap().id > soda.id);
+
+            drop(db);
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Pool of reusable resources shared between threads
+//!
+//! Used to share database connections: a connection can't be used by several threads at the
+//! same time, but a handful of them is enough to serve all the workers of the server without
+//! opening a new one for each request.
+//!
+//! Resources are opened lazily, up to a maximum size, and checked before being lent so that a
+//! broken connection is replaced rather than handed to a request. A thread waiting for a
+//! resource gives up after a timeout with a ServiceUnavailable error, so that an exhausted pool
+//! turns into a 503 instead of a hung request.
+use crate::errors::{Error, Result};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long `Pool::get` waits for a resource by default
+pub const DEFAULT_CHECKOUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Function opening a new resource
+type Open<T> = Box<dyn Fn() -> Result<T> + Send + Sync>;
+
+/// Function telling whether a resource can still be used
+type HealthCheck<T> = Box<dyn Fn(&mut T) -> bool + Send + Sync>;
+
+/// Set of resources lent to one thread at a time
+pub struct Pool<T> {
+    state: Mutex<State<T>>,
+    returned: Condvar,
+    open: Open<T>,
+    health_check: Option<HealthCheck<T>>,
+    max_size: usize,
+    checkout_timeout: Duration,
+}
+
+/// Mutable part of the pool
+struct State<T> {
+    /// Resources waiting to be lent
+    idle: Vec<T>,
+    /// Number of resources in existence, lent or idle, including the ones being opened
+    size: usize,
+}
+
+impl<T> Pool<T> {
+    /// Create an empty pool opening at most `max_size` resources with the given function
+    ///
+    /// `max_size` must be greater than 0.
+    pub fn new<F>(max_size: usize, open: F) -> Self
+    where
+        F: Fn() -> Result<T> + Send + Sync + 'static,
+    {
+        assert!(max_size > 0, "Pool size must be greater than 0");
+        Pool {
+            state: Mutex::new(State {
+                idle: Vec::new(),
+                size: 0,
+            }),
+            returned: Condvar::new(),
+            open: Box::new(open),
+            health_check: None,
+            max_size,
+            checkout_timeout: DEFAULT_CHECKOUT_TIMEOUT,
+        }
+    }
+
+    /// Set how long `get` waits for a resource before giving up
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Check idle resources with the given function before lending them
+    ///
+    /// Resources failing the check are dropped, and a new one is opened in their place.
+    pub fn health_check<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&mut T) -> bool + Send + Sync + 'static,
+    {
+        self.health_check = Some(Box::new(check));
+        self
+    }
+
+    /// Add an already opened resource to the pool
+    ///
+    /// It counts towards the maximum size like the ones opened by the pool.
+    pub fn with_resource(self, resource: T) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            assert!(state.size < self.max_size, "Pool is already full");
+            state.size += 1;
+            state.idle.push(resource);
+        }
+        self
+    }
+
+    /// Borrow a resource from the pool
+    ///
+    /// An idle resource passing the health check is used if there is one, otherwise a new one is
+    /// opened if the pool isn't full yet. If all the resources are in use, waits for one to be
+    /// returned, up to the checkout timeout after which it fails with ServiceUnavailable. Errors
+    /// opening a resource are returned as is.
+    ///
+    /// The resource goes back to the pool when the guard is dropped.
+    pub fn get(&self) -> Result<Pooled<'_, T>> {
+        let deadline = Instant::now() + self.checkout_timeout;
+        loop {
+            let resource = match self.reserve(deadline)? {
+                Some(idle) => match self.check(idle) {
+                    Some(resource) => resource,
+                    None => {
+                        self.release_slot();
+                        continue;
+                    }
+                },
+                None => self.open_reserved()?,
+            };
+            return Ok(Pooled {
+                pool: self,
+                resource: Some(resource),
+            });
+        }
+    }
+
+    /// Take an idle resource, or reserve a slot to open a new one (None)
+    ///
+    /// The lock is never held while running user code, so it can't be poisoned.
+    fn reserve(&self, deadline: Instant) -> Result<Option<T>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(resource) = state.idle.pop() {
+                return Ok(Some(resource));
+            }
+            if state.size < self.max_size {
+                state.size += 1;
+                return Ok(None);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::ServiceUnavailable(format!(
+                    "All {} connections in use, none returned within {:?}",
+                    self.max_size, self.checkout_timeout
+                ))
+                .into());
+            }
+            state = self.returned.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+
+    /// Open a resource in a slot reserved by `reserve`, releasing the slot on failure
+    fn open_reserved(&self) -> Result<T> {
+        (self.open)().inspect_err(|_| self.release_slot())
+    }
+
+    /// Run the health check on a resource, dropping it if it fails
+    fn check(&self, mut resource: T) -> Option<T> {
+        match &self.health_check {
+            Some(check) if !check(&mut resource) => None,
+            _ => Some(resource),
+        }
+    }
+
+    /// Forget a resource that was dropped, letting a waiting thread open a new one
+    fn release_slot(&self) {
+        self.state.lock().unwrap().size -= 1;
+        self.returned.notify_one();
+    }
+
+    /// Put a resource back in the pool and wake up a thread waiting for one
+    fn put_back(&self, resource: T) {
+        self.state.lock().unwrap().idle.push(resource);
+        self.returned.notify_one();
+    }
+}
+
+/// Resource borrowed from a pool
+pub struct Pooled<'a, T> {
+    pool: &'a Pool<T>,
+    // Only None while being dropped
+    resource: Option<T>,
+}
+
+impl<T> Deref for Pooled<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.resource.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for Pooled<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.resource.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for Pooled<'_, T> {
+    fn drop(&mut self) {
+        if let Some(resource) = self.resource.take() {
+            self.pool.put_back(resource);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Pool of numbers counting up from 0
+    fn counting_pool(max_size: usize) -> Pool<u32> {
+        let next = AtomicU32::new(0);
+        Pool::new(max_size, move || Ok(next.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    #[test]
+    fn test_pool_lends_each_resource_once() {
+        let pool = counting_pool(2);
+
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        assert_ne!(*first, *second);
+
+        drop(first);
+        let third = pool.get().unwrap();
+        assert_ne!(*third, *second);
+        // The returned resource is reused rather than opening a new one
+        assert!(*third < 2);
+    }
+
+    #[test]
+    fn test_pool_waits_for_returned_resource() {
+        let pool = Arc::new(counting_pool(1));
+        let guard = pool.get().unwrap();
+
+        let handle = {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                let mut resource = pool.get().unwrap();
+                *resource += 10;
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(*guard, 0);
+        drop(guard);
+        handle.join().unwrap();
+        assert_eq!(*pool.get().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_pool_checkout_timeout() {
+        let pool = counting_pool(1).checkout_timeout(Duration::from_millis(20));
+        let _guard = pool.get().unwrap();
+
+        let start = Instant::now();
+        let err = pool.get().err().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ServiceUnavailable(_))
+        ));
+    }
+
+    #[test]
+    fn test_pool_health_check() {
+        // Resources tampered with are broken
+        let pool = counting_pool(1).health_check(|resource| *resource < 10);
+
+        let mut guard = pool.get().unwrap();
+        assert_eq!(*guard, 0);
+        *guard = 10;
+        drop(guard);
+
+        // The broken resource is replaced by a new one
+        assert_eq!(*pool.get().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_pool_open_failure() {
+        let pool = Pool::<u32>::new(1, || {
+            Err(Error::InternalServerError("Can't open".to_string()).into())
+        })
+        .checkout_timeout(Duration::ZERO);
+        assert!(pool.get().is_err());
+        // The slot of the failed resource is available again
+        let pool = pool.with_resource(7);
+        assert_eq!(*pool.get().unwrap(), 7);
+    }
+}
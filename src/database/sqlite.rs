@@ -0,0 +1,655 @@
+//! Database implementation backed by SQLite
+//!
+//! Orders are stored in two tables: `tables` lists the tables that ordered at least once, and
+//! `items` holds the ordered items along with the table they belong to, when they were ordered
+//! and how long they take to prepare. Item ids are assigned by SQLite. The schema itself is
+//! defined by the migrations. The `menu` table holds the entries that can be ordered, and
+//! `archived_items` the items of the tables that were checked out.
+//!
+//! Requests are served by a pool of connections, see `pool::Pool`. The database is in WAL mode
+//! so that reads don't wait for writes, and writes wait for each other rather than failing
+//! right away.
+use super::migrations::{self, Migration};
+use super::pool::Pool;
+use super::{duplicate_entry, entry_not_found, no_orders, pick_items, units, Database, StoredItem};
+use crate::api::{Item, ItemStatus, MenuEntry, Order, OrderLine};
+use crate::clock::{Clock, SystemClock};
+use crate::errors::{Error, Result};
+use crate::random::{RandomSource, ThreadRandom};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Type, ValueRef};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row, ToSql, TransactionBehavior};
+use std::time::Duration;
+
+/// Database stored in an SQLite file
+pub struct SqliteDB {
+    pool: Pool<Connection>,
+    clock: Box<dyn Clock>,
+    random: Box<dyn RandomSource>,
+}
+
+/// Path opening a private in-memory database
+const IN_MEMORY: &str = ":memory:";
+
+/// How long a write waits for the other writes to complete before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Open a single connection to the database, configured for use in the pool
+fn open_connection(path: &str) -> Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+    Ok(connection)
+}
+
+impl SqliteDB {
+    /// Open the database at the given path, creating it if necessary
+    ///
+    /// The pending migrations are applied before returning. Databases created by a more recent
+    /// version of the application are refused, see `migrations::migrate`.
+    ///
+    /// The special path `:memory:` opens a private in-memory database, which is lost once
+    /// closed. Every connection to `:memory:` would get its own database, so the pool only has
+    /// one in this case.
+    ///
+    /// Otherwise connections are opened as needed, up to one per available CPU, and checked
+    /// before each use.
+    pub fn open(path: &str) -> Result<Self> {
+        let mut connection = open_connection(path)?;
+        migrations::migrate(&mut connection)?;
+        if path == IN_MEMORY {
+            let pool = Pool::new(1, || {
+                Err(Error::InternalServerError(
+                    "The in-memory database can't be reopened".to_string(),
+                )
+                .into())
+            });
+            return Ok(SqliteDB::with_pool(pool.with_resource(connection)));
+        }
+
+        // The journal mode is stored in the database file, no need to repeat it on each
+        // connection
+        connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        let size = std::thread::available_parallelism().map_or(4, |size| size.get());
+        let path = path.to_string();
+        let pool = Pool::new(size, move || open_connection(&path))
+            .health_check(|connection| connection.query_row("SELECT 1", [], |_| Ok(())).is_ok())
+            .with_resource(connection);
+        Ok(SqliteDB::with_pool(pool))
+    }
+
+    fn with_pool(pool: Pool<Connection>) -> Self {
+        SqliteDB {
+            pool,
+            clock: Box::new(SystemClock),
+            random: Box::new(ThreadRandom),
+        }
+    }
+
+    /// Use the given clock to date the items, instead of the system clock
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Use the given source to pick the preparation times, instead of the thread's generator
+    pub fn with_random(mut self, random: impl RandomSource + 'static) -> Self {
+        self.random = Box::new(random);
+        self
+    }
+
+    /// List the migrations that opening the database at the given path would apply
+    ///
+    /// The database is not modified, nor created if it doesn't exist.
+    pub fn pending_migrations(path: &str) -> Result<&'static [Migration]> {
+        if path != IN_MEMORY && !std::path::Path::new(path).exists() {
+            return Ok(migrations::MIGRATIONS);
+        }
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        migrations::pending(&connection)
+    }
+}
+
+/// Statuses are stored by name
+impl ToSql for ItemStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for ItemStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: String| FromSqlError::Other(err.into()))
+    }
+}
+
+/// Convert a row made of the id, name, order time, preparation time, status, note, modifiers
+/// and price of an item
+fn to_item(row: &Row) -> rusqlite::Result<StoredItem> {
+    let modifiers: String = row.get(6)?;
+    Ok(StoredItem {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_at: row.get(2)?,
+        prep_time: row.get(3)?,
+        status: row.get(4)?,
+        note: row.get(5)?,
+        modifiers: serde_json::from_str(&modifiers)
+            .map_err(|err| rusqlite::Error::FromSqlConversionFailure(6, Type::Text, err.into()))?,
+        price: row.get(7)?,
+    })
+}
+
+/// Find an item of a table
+fn find_item(connection: &Connection, table_id: u32, item_id: u32) -> Result<StoredItem> {
+    connection
+        .prepare_cached(
+            "SELECT id, name, created_at, prep_time, status, note, modifiers, price FROM items \
+             WHERE table_id = ?1 AND id = ?2",
+        )?
+        .query_row([table_id, item_id], to_item)
+        .optional()?
+        .ok_or_else(|| {
+            Error::NotFound(format!(
+                "No item with id {} for table {}",
+                item_id, table_id
+            ))
+            .into()
+        })
+}
+
+/// Convert a row made of the name, preparation time range, availability and price of a menu
+/// entry
+fn to_menu_entry(row: &Row) -> rusqlite::Result<MenuEntry> {
+    Ok(MenuEntry {
+        name: row.get(0)?,
+        min_prep_time: row.get(1)?,
+        max_prep_time: row.get(2)?,
+        available: row.get(3)?,
+        price: row.get(4)?,
+    })
+}
+
+/// Find a menu entry by name
+fn find_menu_entry(connection: &Connection, name: &str) -> Result<Option<MenuEntry>> {
+    Ok(connection
+        .prepare_cached(
+            "SELECT name, min_prep_time, max_prep_time, available, price FROM menu \
+             WHERE name = ?1",
+        )?
+        .query_row([name], to_menu_entry)
+        .optional()?)
+}
+
+/// Convert an id assigned by SQLite to the type used by the API
+fn to_id(rowid: i64) -> Result<u32> {
+    u32::try_from(rowid)
+        .map_err(|_| Error::InternalServerError(format!("Item id {} out of range", rowid)).into())
+}
+
+impl Database for SqliteDB {
+    fn new() -> Result<Self> {
+        SqliteDB::open(IN_MEMORY)
+    }
+
+    fn get_order(&self, table_id: u32) -> Result<Order> {
+        let connection = self.pool.get()?;
+        let mut statement = connection.prepare_cached(
+            "SELECT id, name, created_at, prep_time, status, note, modifiers, price FROM items \
+             WHERE table_id = ?1 ORDER BY id",
+        )?;
+        let now = self.clock.now();
+        let items = statement
+            .query_map([table_id], to_item)?
+            .map(|item| item.map(|item| item.to_api(now)))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        if items.is_empty() {
+            Err(no_orders(table_id).into())
+        } else {
+            Ok(Order {
+                items,
+                table_number: table_id,
+            })
+        }
+    }
+
+    fn get_order_item(&self, table_id: u32, order_id: u32) -> Result<Item> {
+        let item = find_item(&*self.pool.get()?, table_id, order_id)?;
+        Ok(item.to_api(self.clock.now()))
+    }
+
+    fn insert_order(&self, item: &str, table_id: u32) -> Result<Item> {
+        let mut items = self.insert_orders(vec![OrderLine::new(item)], table_id)?;
+        Ok(items.remove(0))
+    }
+
+    /// Insert all the items in a single transaction, either all of them are added or none
+    fn insert_orders(&self, lines: Vec<OrderLine>, table_id: u32) -> Result<Vec<Item>> {
+        let mut connection = self.pool.get()?;
+        // Take the write lock right away, upgrading a read transaction can fail without waiting
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let picks = pick_items(&lines, &*self.random, |name| {
+            find_menu_entry(&transaction, name)
+        })?;
+        transaction.execute("INSERT OR IGNORE INTO tables (id) VALUES (?1)", [table_id])?;
+
+        let created_at = self.clock.now();
+        let mut result = Vec::with_capacity(picks.len());
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT INTO items (table_id, name, created_at, prep_time, note, modifiers, price) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for (line, pick) in units(&lines).zip(picks) {
+                let modifiers = serde_json::to_string(&line.modifiers)?;
+                let rowid = statement.insert(params![
+                    table_id,
+                    line.name,
+                    created_at,
+                    pick.prep_time,
+                    line.note,
+                    modifiers,
+                    pick.price
+                ])?;
+                let item = StoredItem {
+                    id: to_id(rowid)?,
+                    name: line.name.clone(),
+                    created_at,
+                    prep_time: pick.prep_time,
+                    status: ItemStatus::Queued,
+                    note: line.note.clone(),
+                    modifiers: line.modifiers.clone(),
+                    price: pick.price,
+                };
+                result.push(item.to_api(created_at));
+            }
+        }
+
+        transaction.commit()?;
+        Ok(result)
+    }
+
+    fn delete_item(&self, table_id: u32, order_id: u32) -> Result<Item> {
+        let mut connection = self.pool.get()?;
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let item = find_item(&transaction, table_id, order_id)?;
+        item.check_deletable()?;
+
+        transaction.execute("DELETE FROM items WHERE id = ?1", [item.id])?;
+        transaction.commit()?;
+        Ok(item.to_api(self.clock.now()))
+    }
+
+    fn update_status(&self, table_id: u32, order_id: u32, status: ItemStatus) -> Result<Item> {
+        let mut connection = self.pool.get()?;
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let item = find_item(&transaction, table_id, order_id)?;
+        item.check_transition(status)?;
+
+        transaction.execute(
+            "UPDATE items SET status = ?1 WHERE id = ?2",
+            params![status, item.id],
+        )?;
+        transaction.commit()?;
+        Ok(StoredItem { status, ..item }.to_api(self.clock.now()))
+    }
+
+    fn checkout(&self, table_id: u32) -> Result<Vec<Item>> {
+        let mut connection = self.pool.get()?;
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let items = transaction
+            .prepare_cached(
+                "SELECT id, name, created_at, prep_time, status, note, modifiers, price FROM items \
+                 WHERE table_id = ?1 ORDER BY id",
+            )?
+            .query_map([table_id], to_item)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if items.is_empty() {
+            return Err(no_orders(table_id).into());
+        }
+
+        let paid_at = self.clock.now();
+        transaction.execute(
+            "INSERT INTO archived_items \
+             SELECT id, table_id, name, created_at, prep_time, status, note, modifiers, price, ?2 \
+             FROM items WHERE table_id = ?1",
+            params![table_id, paid_at],
+        )?;
+        transaction.execute("DELETE FROM items WHERE table_id = ?1", [table_id])?;
+        transaction.commit()?;
+        Ok(items.iter().map(|item| item.to_api(paid_at)).collect())
+    }
+
+    fn get_menu(&self) -> Result<Vec<MenuEntry>> {
+        let connection = self.pool.get()?;
+        let mut statement = connection.prepare_cached(
+            "SELECT name, min_prep_time, max_prep_time, available, price FROM menu ORDER BY name",
+        )?;
+        let entries = statement
+            .query_map([], to_menu_entry)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    fn get_menu_entry(&self, name: &str) -> Result<MenuEntry> {
+        find_menu_entry(&*self.pool.get()?, name)?.ok_or_else(|| entry_not_found(name).into())
+    }
+
+    fn add_menu_entry(&self, entry: MenuEntry) -> Result<MenuEntry> {
+        let inserted = self.pool.get()?.execute(
+            "INSERT OR IGNORE INTO menu (name, min_prep_time, max_prep_time, available, price) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                entry.name,
+                entry.min_prep_time,
+                entry.max_prep_time,
+                entry.available,
+                entry.price
+            ],
+        )?;
+        if inserted == 0 {
+            return Err(duplicate_entry(&entry.name).into());
+        }
+        Ok(entry)
+    }
+
+    fn update_menu_entry(&self, entry: MenuEntry) -> Result<MenuEntry> {
+        let updated = self.pool.get()?.execute(
+            "UPDATE menu SET min_prep_time = ?2, max_prep_time = ?3, available = ?4, price = ?5 \
+             WHERE name = ?1",
+            params![
+                entry.name,
+                entry.min_prep_time,
+                entry.max_prep_time,
+                entry.available,
+                entry.price
+            ],
+        )?;
+        if updated == 0 {
+            return Err(entry_not_found(&entry.name).into());
+        }
+        Ok(entry)
+    }
+
+    fn delete_menu_entry(&self, name: &str) -> Result<MenuEntry> {
+        self.pool
+            .get()?
+            .query_row(
+                "DELETE FROM menu WHERE name = ?1 \
+                 RETURNING name, min_prep_time, max_prep_time, available, price",
+                [name],
+                to_menu_entry,
+            )
+            .optional()?
+            .ok_or_else(|| entry_not_found(name).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+    use crate::database::add_to_menu;
+    use crate::random::SeededRandom;
+
+    #[test]
+    fn test_sqlite_db() {
+        let db = SqliteDB::new().unwrap();
+        add_to_menu(&db, &["Pizza", "Burger", "Pasta"]);
+        let pizza_id = db.insert_order("Pizza", 1).unwrap().id;
+        let burger_id = db.insert_order("Burger", 2).unwrap().id;
+        let pasta_id = db.insert_order("Pasta", 1).unwrap().id;
+
+        let result = db.get_order(1).unwrap();
+        assert_eq!(result.items.len(), 2);
+        assert_eq!(result.items[0].name, "Pizza");
+        assert_eq!(result.items[0].id, pizza_id);
+        assert_eq!(result.items[1].name, "Pasta");
+        assert_eq!(result.items[1].id, pasta_id);
+
+        let result = db.get_order(2).unwrap();
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].id, burger_id);
+
+        assert!(db.get_order(3).is_err());
+
+        assert_eq!(db.get_order_item(1, pasta_id).unwrap().name, "Pasta");
+        assert!(db.get_order_item(2, pasta_id).is_err());
+
+        assert_eq!(db.delete_item(1, pizza_id).unwrap().name, "Pizza");
+        assert!(db.delete_item(1, pizza_id).is_err());
+        assert!(db.delete_item(1, burger_id).is_err());
+        assert!(db.delete_item(2, burger_id).is_ok());
+    }
+
+    #[test]
+    fn test_countdown() {
+        let clock = FakeClock::new(1_000_000);
+        let db = SqliteDB::new()
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_random(SeededRandom::new(1));
+        add_to_menu(&db, &["Pizza"]);
+        let prep_time = SeededRandom::new(1).gen_range(5..15);
+
+        let item = db.insert_order("Pizza", 1).unwrap();
+        assert_eq!(item.time_to_completion, prep_time);
+        assert_eq!(item.ready_at, 1_000_000 + u64::from(prep_time) * 60);
+
+        clock.advance(Duration::from_secs(60));
+        let later = db.get_order_item(1, item.id).unwrap();
+        assert_eq!(later.time_to_completion, prep_time - 1);
+        assert_eq!(later.ready_at, item.ready_at);
+
+        clock.set(item.ready_at + 1);
+        assert_eq!(db.delete_item(1, item.id).unwrap().time_to_completion, 0);
+    }
+
+    #[test]
+    fn test_item_status() {
+        let db = SqliteDB::new().unwrap();
+        add_to_menu(&db, &["Pizza"]);
+        let item = db.insert_order("Pizza", 1).unwrap();
+        assert_eq!(item.status, ItemStatus::Queued);
+
+        assert!(db.update_status(1, item.id, ItemStatus::Ready).is_err());
+        for status in [ItemStatus::Cooking, ItemStatus::Ready, ItemStatus::Served] {
+            assert_eq!(db.update_status(1, item.id, status).unwrap().status, status);
+        }
+        assert_eq!(
+            db.get_order_item(1, item.id).unwrap().status,
+            ItemStatus::Served
+        );
+
+        let err = db.delete_item(1, item.id).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Conflict(_))
+        ));
+        assert!(db.get_order_item(1, item.id).is_ok());
+        assert!(db.update_status(2, item.id, ItemStatus::Cancelled).is_err());
+    }
+
+    #[test]
+    fn test_insert_orders_is_transactional() {
+        let db = SqliteDB::new().unwrap();
+        add_to_menu(&db, &["Pizza", "Soda"]);
+
+        let items = vec![OrderLine::new("Pizza"), OrderLine::new("")];
+        assert!(db.insert_orders(items, 1).is_err());
+        assert!(db.get_order(1).is_err());
+
+        let pizzas = OrderLine {
+            name: "Pizza".to_string(),
+            quantity: 2,
+            note: Some("well done".to_string()),
+            modifiers: vec!["extra cheese".to_string()],
+        };
+        let items = db
+            .insert_orders(vec![pizzas, OrderLine::new("Soda")], 1)
+            .unwrap();
+        assert_ne!(items[0].id, items[1].id);
+        let order = db.get_order(1).unwrap();
+        assert_eq!(order.items.len(), 3);
+        assert_eq!(order.items[1].note.as_deref(), Some("well done"));
+        assert_eq!(order.items[1].modifiers, ["extra cheese"]);
+        assert_eq!(order.items[2].note, None);
+        assert!(order.items[2].modifiers.is_empty());
+    }
+
+    #[test]
+    fn test_checkout() {
+        let db = SqliteDB::new().unwrap();
+        add_to_menu(&db, &["Pizza", "Soda"]);
+        let items = db
+            .insert_orders(vec![OrderLine::new("Pizza"), OrderLine::new("Soda")], 1)
+            .unwrap();
+        db.insert_order("Pizza", 2).unwrap();
+
+        for status in [ItemStatus::Cooking, ItemStatus::Ready, ItemStatus::Served] {
+            db.update_status(1, items[0].id, status).unwrap();
+        }
+
+        // Items not served yet are paid for all the same
+        let paid = db.checkout(1).unwrap();
+        assert_eq!(paid.len(), 2);
+        assert_eq!(paid[0].price, 1000);
+        assert_eq!(paid[1].status, ItemStatus::Queued);
+        assert!(db.get_order(1).is_err());
+        let archived: u32 = db
+            .pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT count(*) FROM archived_items WHERE table_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(archived, 2);
+        let err = db.checkout(1).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+        assert_eq!(db.get_order(2).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn test_menu() {
+        let db = SqliteDB::new().unwrap();
+        add_to_menu(&db, &["Soda", "Pizza"]);
+        let soup = MenuEntry {
+            name: "Soup".to_string(),
+            min_prep_time: 3,
+            max_prep_time: 3,
+            available: true,
+            price: 450,
+        };
+        assert_eq!(db.add_menu_entry(soup.clone()).unwrap(), soup);
+        let err = db.add_menu_entry(soup.clone()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Conflict(_))
+        ));
+        let names: Vec<_> = db
+            .get_menu()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["Pizza", "Soda", "Soup"]);
+
+        let item = db.insert_order("Soup", 1).unwrap();
+        assert_eq!((item.time_to_completion, item.price), (3, 450));
+
+        let soup = MenuEntry {
+            available: false,
+            ..soup
+        };
+        assert_eq!(db.update_menu_entry(soup.clone()).unwrap(), soup);
+        assert_eq!(db.get_menu_entry("Soup").unwrap(), soup);
+        let unknown = MenuEntry {
+            name: "Sushi".to_string(),
+            ..soup.clone()
+        };
+        assert!(db.update_menu_entry(unknown).is_err());
+
+        assert_eq!(db.delete_menu_entry("Soda").unwrap().name, "Soda");
+        assert!(db.delete_menu_entry("Soda").is_err());
+        assert!(db.get_menu_entry("Soda").is_err());
+
+        let items = ["Pizza", "Soup", "Soda"].map(OrderLine::new).to_vec();
+        match db
+            .insert_orders(items, 2)
+            .unwrap_err()
+            .downcast_ref::<Error>()
+        {
+            Some(Error::UnavailableItems(names)) => assert_eq!(names, &["Soup", "Soda"]),
+            err => panic!("Unexpected error {:?}", err),
+        }
+        assert!(db.get_order(2).is_err());
+        // Items already ordered are kept
+        assert_eq!(db.get_order(1).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        let path = std::env::temp_dir().join(format!("orders-{}-mt.sqlite", std::process::id()));
+        let path = path.to_str().unwrap();
+        let db = SqliteDB::open(path).unwrap();
+        add_to_menu(&db, &["Pizza", "Soda"]);
+
+        std::thread::scope(|scope| {
+            for table in 0..4 {
+                let db = &db;
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        db.insert_orders(
+                            vec![OrderLine::new("Pizza"), OrderLine::new("Soda")],
+                            table,
+                        )
+                        .unwrap();
+                        db.get_order(table).unwrap();
+                    }
+                });
+            }
+        });
+
+        for table in 0..4 {
+            assert_eq!(db.get_order(table).unwrap().items.len(), 20);
+        }
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = std::env::temp_dir().join(format!("orders-{}.sqlite", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let item = {
+            let db = SqliteDB::open(path).unwrap();
+            add_to_menu(&db, &["Pizza", "Soda"]);
+            db.insert_order("Pizza", 1).unwrap()
+        };
+
+        let db = SqliteDB::open(path).unwrap();
+        assert_eq!(db.get_order_item(1, item.id).unwrap().name, "Pizza");
+        assert_eq!(db.get_menu().unwrap().len(), 2);
+        // Ids of deleted items are not reused
+        db.delete_item(1, item.id).unwrap();
+        assert!(db.insert_order("Soda", 1).unwrap().id > item.id);
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+}
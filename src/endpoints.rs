@@ -1,139 +1,242 @@
 use crate::api::*;
+use crate::billing::{self, TaxRate};
 use crate::database::Database;
 use crate::errors::{Error, Result};
-use crate::http::{Request, Response};
+use crate::http::{Method, Response};
 use crate::routes::*;
 
+/// Settings of the application, as opposed to its state kept in the database
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Config {
+    /// Tax applied to the bills
+    pub tax_rate: TaxRate,
+}
+
 /// Create the router for the HTTP server.
 ///
 /// This is where we define the associations between the HTTP methods and the handlers.
 /// Nothing in this function should fail if the application is correctly implemented,
-pub fn create_http_router() -> Result<HttpRouter> {
+///
+/// The handlers work with any database implementation, including trait objects.
+pub fn create_http_router<D: Database + ?Sized + 'static>(config: Config) -> Result<HttpRouter<D>> {
     let mut router = HttpRouter::new()?;
 
-    router.add_route("POST", endpoints::ORDERS, new_order);
-    router.add_route("GET", endpoints::ORDER_BY_ID, get_items);
-    router.add_route("GET", endpoints::ITEM_BY_ID, get_order_item);
-    router.add_route("DELETE", endpoints::ITEM_BY_ID, delete_order_item);
+    router.add_route(Method::Post, endpoints::ORDERS, extract(new_order));
+    router.add_route(Method::Get, endpoints::ORDER_BY_ID, extract(get_items));
+    router.add_route(Method::Get, endpoints::ITEM_BY_ID, extract(get_order_item));
+    router.add_route(Method::Delete, endpoints::ITEM_BY_ID, extract(delete_order_item));
+    router.add_route(Method::Patch, endpoints::ITEM_BY_ID, extract(update_order_item));
+    router.add_route(
+        Method::Get,
+        endpoints::BILL,
+        extract(move |path, db: &D| get_bill(path, db, config.tax_rate)),
+    );
+    router.add_route(
+        Method::Post,
+        endpoints::SPLIT_BILL,
+        extract(move |body, db: &D| split_bill(body, db, config.tax_rate)),
+    );
+    router.add_route(
+        Method::Post,
+        endpoints::CHECKOUT,
+        extract(move |path, db: &D| checkout(path, db, config.tax_rate)),
+    );
+    router.add_route(Method::Get, endpoints::MENU, extract(get_menu));
+    router.add_route(Method::Post, endpoints::MENU, extract(add_menu_entry));
+    router.add_route(Method::Get, endpoints::MENU_ENTRY, extract(get_menu_entry));
+    router.add_route(Method::Put, endpoints::MENU_ENTRY, extract(update_menu_entry));
+    router.add_route(Method::Delete, endpoints::MENU_ENTRY, extract(delete_menu_entry));
 
     Ok(router)
 }
 
-/// Extract a numeric identifier from the request parameters
-fn get_id(params: &HttpParams, key: &str) -> Result<u32> {
-    params
-        .get(key)
-        .ok_or(Error::BadRequest(format!("Missing '{}'", key)))
-        .and_then(|id| {
-            id.parse::<u32>()
-                .map_err(|err| Error::BadRequest(err.to_string()))
-        })
-        .map_err(|err| err.into())
-}
-
-/// Serialize a value to a JSON string,
-///
-/// Returns an InternalServerError if it fails, as this would be a programming error.
-fn serialize<T: serde::Serialize>(data: T) -> Result<String> {
-    serde_json::to_string(&data).map_err(|err| Error::InternalServerError(err.to_string()).into())
-}
-
 /// Handle requests for creation of a new order
-fn new_order(req: Request, _: HttpParams, db: &mut dyn Database) -> Result<Response> {
-    let body = serde_json::from_str::<NewOrder>(&req.body)
-        .map_err(|err| Error::BadRequest(err.to_string()))?;
-
+fn new_order<D: Database + ?Sized>(Json(body): Json<NewOrder>, db: &D) -> Result<Response> {
+    body.validate()?;
     db.insert_orders(body.items, body.table_number)
         .map(|vec| Order {
             table_number: body.table_number,
             items: vec,
         })
-        .and_then(&serialize)
-        .map(Response::ok_with_body)
-        .and_then(Ok)
+        .and_then(|data| Response::json(&data))
 }
 
 /// Handle requests for the items associated to an order (table id)
-fn get_items(_: Request, params: HttpParams, db: &mut dyn Database) -> Result<Response> {
-    let order_id = get_id(&params, params::ORDER_ID)?;
-
+fn get_items<D: Database + ?Sized>(Path(order_id): Path<u32>, db: &D) -> Result<Response> {
     db.get_order(order_id)
-        .and_then(&serialize)
-        .map(Response::ok_with_body)
-        .and_then(Ok)
+        .and_then(|data| Response::json(&data))
 }
 
 /// Handle requests to fetch a specific item
-fn get_order_item(_: Request, params: HttpParams, db: &mut dyn Database) -> Result<Response> {
-    let order_id = get_id(&params, params::ORDER_ID)?;
-    let item_id = get_id(&params, params::ITEM_ID)?;
-
+fn get_order_item<D: Database + ?Sized>(
+    Path((order_id, item_id)): Path<(u32, u32)>,
+    db: &D,
+) -> Result<Response> {
     db.get_order_item(order_id, item_id)
-        .and_then(&serialize)
-        .map(Response::ok_with_body)
-        .and_then(Ok)
+        .and_then(|data| Response::json(&data))
 }
 
 /// Handle requests to delete an item from a table order
-fn delete_order_item(_: Request, params: HttpParams, db: &mut dyn Database) -> Result<Response> {
-    let order_id = get_id(&params, params::ORDER_ID)?;
-    let item_id = get_id(&params, params::ITEM_ID)?;
-
+fn delete_order_item<D: Database + ?Sized>(
+    Path((order_id, item_id)): Path<(u32, u32)>,
+    db: &D,
+) -> Result<Response> {
     db.delete_item(order_id, item_id)
-        .and_then(&serialize)
-        .map(Response::ok_with_body)
-        .and_then(Ok)
+        .and_then(|data| Response::json(&data))
+}
+
+/// Handle requests to change the status of an item
+fn update_order_item<D: Database + ?Sized>(
+    (Path((order_id, item_id)), Json(body)): (Path<(u32, u32)>, Json<UpdateItem>),
+    db: &D,
+) -> Result<Response> {
+    db.update_status(order_id, item_id, body.status)
+        .and_then(|data| Response::json(&data))
+}
+
+/// Handle requests for the bill of a table
+fn get_bill<D: Database + ?Sized>(
+    Path(order_id): Path<u32>,
+    db: &D,
+    tax_rate: TaxRate,
+) -> Result<Response> {
+    let order = db.get_order(order_id)?;
+    Response::json(&billing::bill(order_id, &order.items, tax_rate))
+}
+
+/// Handle requests to split the bill of a table between guests
+fn split_bill<D: Database + ?Sized>(
+    (Path(order_id), Json(body)): (Path<u32>, Json<SplitBill>),
+    db: &D,
+    tax_rate: TaxRate,
+) -> Result<Response> {
+    body.validate()?;
+    let order = db.get_order(order_id)?;
+    let bill = billing::bill(order_id, &order.items, tax_rate);
+    Response::json(&billing::split(bill, &body)?)
+}
+
+/// Handle requests to pay and close a table
+///
+/// Responds with the bill of the archived items.
+fn checkout<D: Database + ?Sized>(
+    Path(order_id): Path<u32>,
+    db: &D,
+    tax_rate: TaxRate,
+) -> Result<Response> {
+    let items = db.checkout(order_id)?;
+    Response::json(&billing::bill(order_id, &items, tax_rate))
+}
+
+/// Handle requests for the whole menu
+fn get_menu<D: Database + ?Sized>(_: (), db: &D) -> Result<Response> {
+    db.get_menu().and_then(|data| Response::json(&data))
+}
+
+/// Handle requests to add an entry to the menu
+fn add_menu_entry<D: Database + ?Sized>(Json(entry): Json<MenuEntry>, db: &D) -> Result<Response> {
+    entry.validate()?;
+    db.add_menu_entry(entry)
+        .and_then(|data| Response::json(&data))
+}
+
+/// Handle requests for a menu entry
+fn get_menu_entry<D: Database + ?Sized>(Path(name): Path<String>, db: &D) -> Result<Response> {
+    db.get_menu_entry(&name)
+        .and_then(|data| Response::json(&data))
+}
+
+/// Handle requests to replace a menu entry
+///
+/// The entry can't be renamed, the name in the body must be the one in the path.
+fn update_menu_entry<D: Database + ?Sized>(
+    (Path(name), Json(entry)): (Path<String>, Json<MenuEntry>),
+    db: &D,
+) -> Result<Response> {
+    if entry.name != name {
+        return Err(Error::InvalidField {
+            field: "name".to_string(),
+            message: format!("expected '{}', the name of the entry in the path", name),
+        }
+        .into());
+    }
+    entry.validate()?;
+    db.update_menu_entry(entry)
+        .and_then(|data| Response::json(&data))
+}
+
+/// Handle requests to remove an entry from the menu
+fn delete_menu_entry<D: Database + ?Sized>(Path(name): Path<String>, db: &D) -> Result<Response> {
+    db.delete_menu_entry(&name)
+        .and_then(|data| Response::json(&data))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::{FakeClock, Timestamp};
+    use crate::database::add_to_menu;
     use crate::database::mock::MockDB;
+    use crate::http::Request;
+    use crate::random::{RandomSource, SeededRandom};
+    use std::time::Duration;
+
+    /// Time at which the clock of the test databases starts
+    const START: Timestamp = 1_700_000_000;
+
+    /// Seed of the random preparation times of the test databases
+    const SEED: u64 = 42;
 
     fn to_item(resp: &Response) -> Result<Item> {
-        serde_json::from_str(&resp.body).map_err(|e| e.into())
+        resp.json_body()
     }
     fn to_order(resp: &Response) -> Result<Order> {
-        serde_json::from_str(&resp.body).map_err(|e| e.into())
+        resp.json_body()
+    }
+
+    /// Preparation times given to the first items inserted in the test databases
+    fn prep_times(count: usize) -> Vec<u32> {
+        let random = SeededRandom::new(SEED);
+        (0..count).map(|_| random.gen_range(5..15)).collect()
+    }
+
+    /// When an item inserted at START with the given preparation time is ready
+    fn ready_at(prep_time: u32) -> Timestamp {
+        START + u64::from(prep_time) * 60
     }
 
     macro_rules! make_db {
-        () => {
-            MockDB::new().unwrap()
-        };
+        () => {{
+            let clock = FakeClock::new(START);
+            let db = MockDB::new()
+                .unwrap()
+                .with_clock(clock.clone())
+                .with_random(SeededRandom::new(SEED));
+            add_to_menu(&db, &["Pizza", "Burger", "Soda", "Sushi", "Pasta"]);
+            (db, clock)
+        }};
 
         ( $( ($table_number:literal: $order:expr $(, $orders:expr)*))*)=> {{
-            let mut db = MockDB::new().unwrap();
+            let (db, clock) = make_db!();
             $(
                 db.insert_orders(
-                    vec![$order.to_string(), $($orders.to_string(),)* ], $table_number)
+                    vec![OrderLine::new($order), $(OrderLine::new($orders),)* ], $table_number)
                 .unwrap();
             )*
-            db
+            (db, clock)
         }};
     }
 
-    fn request_from<T: serde::Serialize>(obj:&T) -> Request {
-        // We don't actually care about the method in these tests
-        Request::new("GET", "", vec![], serde_json::to_string(obj).unwrap())
-    }
-    fn empty_request() -> Request {
-        Request::new("GET", "", vec![], "".to_string())
-    }
-
     #[test]
     fn test_get_items() {
-        let mut db = make_db!(
+        let (db, clock) = make_db!(
             (1: "Pizza", "Burger", "Soda")
             (2: "Sushi", "Pizza")
         );
+        let prep_times = prep_times(3);
 
-        let response = get_items(
-            empty_request(),
-            make_params!(ORDER_ID: 1),
-            &mut db,
-        )
-        .unwrap();
+        clock.advance(Duration::from_secs(4 * 60));
+        let response = get_items(Path(1), &db).unwrap();
 
         println!("response: {:?}", response.body);
         let item = to_order(&response).unwrap();
@@ -142,66 +245,387 @@ mod tests {
         assert!(item.items.iter().find(|i| i.name == "Pizza").is_some());
         assert!(item.items.iter().find(|i| i.name == "Burger").is_some());
         assert!(item.items.iter().find(|i| i.name == "Soda").is_some());
+        for (item, prep_time) in item.items.iter().zip(prep_times) {
+            assert_eq!(item.time_to_completion, prep_time - 4);
+            assert_eq!(item.ready_at, ready_at(prep_time));
+        }
     }
 
     #[test]
     fn test_new_order() {
-        let mut db = make_db!();
+        let (db, _) = make_db!();
 
         let new_items = NewOrder {
-            items: vec!["Pizza".to_string(), "Burger".to_string()],
+            items: vec![OrderLine::new("Pizza"), OrderLine::new("Burger")],
             table_number: 1,
         };
 
-        let response = new_order(
-            request_from(&new_items),
-            make_params!(),
-            &mut db,
-        )
-        .unwrap();
+        let response = new_order(Json(new_items), &db).unwrap();
 
         let order = to_order(&response).unwrap();
         assert_eq!(order.table_number, 1);
         assert_eq!(order.items.len(), 2);
         assert!(order.items.iter().find(|i| i.name == "Pizza").is_some());
         assert!(order.items.iter().find(|i| i.name == "Burger").is_some());
+        for (item, prep_time) in order.items.iter().zip(prep_times(2)) {
+            assert_eq!(item.time_to_completion, prep_time);
+            assert_eq!(item.ready_at, ready_at(prep_time));
+        }
     }
 
     #[test]
     fn test_get_order_item() {
-        let mut db = make_db!(
+        let (db, clock) = make_db!(
             (1: "Pizza", "Soda")
             (2: "Sushi", "Burger")
         );
+        let soda_prep_time = prep_times(2)[1];
 
         let item = db.find_by_name("Soda").unwrap();
 
-        let response = get_order_item(
-            empty_request(),
-            make_params!(ORDER_ID: 1, ITEM_ID: item.id),
-            &mut db,
-        ).unwrap();
+        // Less than a minute elapsed, the remaining time is rounded up
+        clock.advance(Duration::from_secs(59));
+        let response = get_order_item(Path((1, item.id)), &db).unwrap();
 
         let item = to_item(&response).unwrap();
         assert_eq!(item.name, "Soda");
+        assert_eq!(item.time_to_completion, soda_prep_time);
+        assert_eq!(item.ready_at, ready_at(soda_prep_time));
     }
 
     #[test]
     fn test_delete_item() {
-        let mut db = make_db!(
+        let (db, clock) = make_db!(
             (1: "Pizza", "Soda")
             (2: "Sushi", "Burger")
         );
+        let pizza_prep_time = prep_times(1)[0];
 
         let item = db.find_by_name("Pizza").unwrap();
 
-        let response = delete_order_item(
-            empty_request(),
-            make_params!(ORDER_ID: 1, ITEM_ID: item.id),
-            &mut db,
-        ).unwrap();
+        clock.set(ready_at(pizza_prep_time) + 60);
+        let response = delete_order_item(Path((1, item.id)), &db).unwrap();
 
         let item = to_item(&response).unwrap();
         assert_eq!(item.name, "Pizza");
+        assert_eq!(item.time_to_completion, 0);
+        assert_eq!(item.ready_at, ready_at(pizza_prep_time));
+    }
+
+    #[test]
+    fn test_update_order_item() {
+        let (db, _) = make_db!((1: "Pizza"));
+        let item = db.find_by_name("Pizza").unwrap();
+        assert_eq!(item.status, ItemStatus::Queued);
+
+        let update =
+            |status| update_order_item((Path((1, item.id)), Json(UpdateItem { status })), &db);
+        for status in [ItemStatus::Cooking, ItemStatus::Ready, ItemStatus::Served] {
+            let response = update(status).unwrap();
+            assert_eq!(to_item(&response).unwrap().status, status);
+        }
+
+        let err = update(ItemStatus::Cancelled).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Conflict(_))
+        ));
+        let err = delete_order_item(Path((1, item.id)), &db).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Conflict(_))
+        ));
+        assert_eq!(
+            db.get_order_item(1, item.id).unwrap().status,
+            ItemStatus::Served
+        );
+
+        let err = update_order_item(
+            (
+                Path((1, item.id + 1)),
+                Json(UpdateItem {
+                    status: ItemStatus::Cooking,
+                }),
+            ),
+            &db,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_patch_route() {
+        let router = create_http_router::<MockDB>(Config::default()).unwrap();
+        let (db, _) = make_db!((1: "Pizza"));
+        let item = db.find_by_name("Pizza").unwrap();
+        let path = item_by_id(1, item.id);
+
+        let response = router
+            .route(Request::patch(&path, r#"{"status": "cooking"}"#), &db)
+            .unwrap();
+        assert_eq!(to_item(&response).unwrap().status, ItemStatus::Cooking);
+
+        let err = router
+            .route(Request::patch(&path, r#"{"status": "burnt"}"#), &db)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidField { .. })
+        ));
+    }
+
+    #[test]
+    fn test_new_order_with_lines() {
+        let router = create_http_router::<MockDB>(Config::default()).unwrap();
+        let (db, _) = make_db!();
+        let body = r#"{"table_number": 1, "items": [
+            "Pizza",
+            {"name": "Soda", "quantity": 2, "note": "no ice", "modifiers": ["lemon"]}
+        ]}"#;
+
+        let response = router
+            .route(Request::post(paths::ORDERS, body), &db)
+            .unwrap();
+        let order = to_order(&response).unwrap();
+        let names: Vec<_> = order.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["Pizza", "Soda", "Soda"]);
+        assert_eq!(order.items[0].note, None);
+        for item in &order.items[1..] {
+            assert_eq!(item.note.as_deref(), Some("no ice"));
+            assert_eq!(item.modifiers, ["lemon"]);
+        }
+        assert_ne!(order.items[1].id, order.items[2].id);
+    }
+
+    #[test]
+    fn test_new_order_with_unknown_items() {
+        let (db, _) = make_db!((1: "Pizza"));
+
+        let new_items = NewOrder {
+            items: ["Piza", "Soda", "Piza"].map(OrderLine::new).to_vec(),
+            table_number: 1,
+        };
+        match *new_order(Json(new_items), &db)
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+        {
+            Error::UnavailableItems(names) => assert_eq!(names, ["Piza"]),
+            err => panic!("Unexpected error {:?}", err),
+        }
+        assert_eq!(db.get_order(1).unwrap().items.len(), 1);
+    }
+
+    #[test]
+    fn test_menu_routes() {
+        let router = create_http_router::<MockDB>(Config::default()).unwrap();
+        let (db, _) = make_db!();
+        let path = menu_entry("Fish & Chips");
+        let entry = r#"{
+            "name": "Fish & Chips", "min_prep_time": 10, "max_prep_time": 10, "available": true
+        }"#;
+        let to_entry = |response: Response| response.json_body::<MenuEntry>().unwrap();
+
+        let response = router
+            .route(Request::post(paths::MENU, entry), &db)
+            .unwrap();
+        assert_eq!(to_entry(response).name, "Fish & Chips");
+        let response = router.route(Request::get(&path), &db).unwrap();
+        assert_eq!(to_entry(response).max_prep_time, 10);
+        let response = router.route(Request::get(paths::MENU), &db).unwrap();
+        assert_eq!(response.json_body::<Vec<MenuEntry>>().unwrap().len(), 6);
+
+        let unavailable = entry.replace("true", "false");
+        let response = router.route(Request::put(&path, unavailable), &db).unwrap();
+        assert!(!to_entry(response).available);
+        assert!(db.insert_order("Fish & Chips", 1).is_err());
+
+        let invalid_field = |request: Request| match *router
+            .route(request, &db)
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+        {
+            Error::InvalidField { field, .. } => field,
+            err => panic!("Unexpected error {:?}", err),
+        };
+        let renamed = entry.replace("Fish & Chips", "Chips");
+        assert_eq!(invalid_field(Request::put(&path, renamed)), "name");
+        let reversed = entry.replace("\"max_prep_time\": 10", "\"max_prep_time\": 5");
+        assert_eq!(
+            invalid_field(Request::put(&path, reversed)),
+            "min_prep_time"
+        );
+
+        let response = router.route(Request::delete(&path, ""), &db).unwrap();
+        assert_eq!(to_entry(response).name, "Fish & Chips");
+        let err = router.route(Request::get(&path), &db).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_menu_entry_with_slash() {
+        let router = create_http_router::<MockDB>(Config::default()).unwrap();
+        let (db, _) = make_db!();
+        let path = menu_entry("A/B");
+        let entry = r#"{"name": "A/B", "min_prep_time": 1, "max_prep_time": 2, "available": true}"#;
+        let to_entry = |response: Response| response.json_body::<MenuEntry>().unwrap();
+
+        router
+            .route(Request::post(paths::MENU, entry), &db)
+            .unwrap();
+        let response = router.route(Request::get(&path), &db).unwrap();
+        assert_eq!(to_entry(response).name, "A/B");
+        let unavailable = entry.replace("true", "false");
+        let response = router.route(Request::put(&path, unavailable), &db).unwrap();
+        assert!(!to_entry(response).available);
+        let response = router.route(Request::delete(&path, ""), &db).unwrap();
+        assert_eq!(to_entry(response).name, "A/B");
+        assert!(db.get_menu_entry("A/B").is_err());
+    }
+
+    #[test]
+    fn test_bill_routes() {
+        let config = Config {
+            tax_rate: "8.25".parse().unwrap(),
+        };
+        let router = create_http_router::<MockDB>(config).unwrap();
+        let (db, _) = make_db!((1: "Pizza", "Soda", "Pizza"));
+        let to_bill = |response: Response| response.json_body::<Bill>().unwrap();
+
+        let bill = to_bill(router.route(Request::get(&order_bill(1)), &db).unwrap());
+        assert_eq!(bill.lines.len(), 2);
+        assert_eq!(
+            (bill.lines[0].name.as_str(), bill.lines[0].quantity),
+            ("Pizza", 2)
+        );
+        assert_eq!(bill.subtotal, 3000);
+        assert_eq!(bill.tax_rate, 825);
+        assert_eq!(bill.tax, 248);
+        assert_eq!(bill.total, 3248);
+
+        // The soda is still queued, it is paid for anyway
+        let order = db.get_order(1).unwrap();
+        for status in [ItemStatus::Cooking, ItemStatus::Ready, ItemStatus::Served] {
+            db.update_status(1, order.items[0].id, status).unwrap();
+        }
+        db.update_status(1, order.items[2].id, ItemStatus::Cancelled)
+            .unwrap();
+        let bill = to_bill(
+            router
+                .route(Request::post(&order_checkout(1), ""), &db)
+                .unwrap(),
+        );
+        assert_eq!(bill.subtotal, 2000);
+        assert_eq!(bill.total, 2165);
+        assert!(db.get_order(1).is_err());
+        let err = router.route(Request::get(&order_bill(1)), &db).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_split_bill_route() {
+        let config = Config {
+            tax_rate: "10".parse().unwrap(),
+        };
+        let router = create_http_router::<MockDB>(config).unwrap();
+        let (db, _) = make_db!((1: "Pizza", "Soda", "Pasta"));
+        let path = order_split_bill(1);
+        let to_shared = |response: Response| response.json_body::<SharedBill>().unwrap();
+
+        let shared = to_shared(
+            router
+                .route(Request::post(&path, r#"{"even": 4}"#), &db)
+                .unwrap(),
+        );
+        assert_eq!(shared.bill.total, 3300);
+        let totals: Vec<_> = shared.guests.iter().map(|guest| guest.total).collect();
+        assert_eq!(totals, [825, 825, 825, 825]);
+
+        let ids: Vec<_> = db
+            .get_order(1)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| item.id)
+            .collect();
+        let by_items = format!(
+            r#"{{"by_items": [[{}, {}], [{}]]}}"#,
+            ids[0], ids[2], ids[1]
+        );
+        let shared = to_shared(router.route(Request::post(&path, by_items), &db).unwrap());
+        let totals: Vec<_> = shared.guests.iter().map(|guest| guest.total).collect();
+        assert_eq!(totals, [2200, 1100]);
+        assert_eq!(shared.guests[1].items, [ids[1]]);
+
+        let invalid_field = |body: &str| match *router
+            .route(Request::post(&path, body), &db)
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+        {
+            Error::InvalidField { field, .. } => field,
+            err => panic!("Unexpected error {:?}", err),
+        };
+        assert_eq!(invalid_field(r#"{"even": 0}"#), "even");
+        assert_eq!(invalid_field(r#"{"by_items": []}"#), "by_items");
+        assert_eq!(invalid_field(r#"{"by_items": [[12345]]}"#), "by_items[0]");
+
+        let err = router
+            .route(Request::post(&order_split_bill(2), r#"{"even": 2}"#), &db)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let router = create_http_router::<MockDB>(Config::default()).unwrap();
+        let (db, _) = make_db!((1: "Pizza"));
+
+        let invalid_field = |request: Request, db: &MockDB| match *router
+            .route(request, db)
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap()
+        {
+            Error::InvalidField { field, .. } => field,
+            err => panic!("Unexpected error {:?}", err),
+        };
+
+        assert_eq!(
+            invalid_field(Request::get("/api/v1/orders/1/items/pizza"), &db),
+            params::ITEM_ID
+        );
+        assert_eq!(
+            invalid_field(
+                Request::post(paths::ORDERS, "{\"table_number\": -1, \"items\": []}"),
+                &db
+            ),
+            "table_number"
+        );
+        let body =
+            r#"{"table_number": 1, "items": ["Soda", {"name": "Pizza", "quantity": "two"}]}"#;
+        assert_eq!(
+            invalid_field(Request::post(paths::ORDERS, body), &db),
+            "items[1].quantity"
+        );
+        let body = r#"{"table_number": 1, "items": [{"name": "Pizza", "quantity": 0}]}"#;
+        assert_eq!(
+            invalid_field(Request::post(paths::ORDERS, body), &db),
+            "items[0].quantity"
+        );
+        assert_eq!(db.get_order(1).unwrap().items.len(), 1);
     }
 }
@@ -1,3 +1,5 @@
+use crate::http::{allow_header, Method};
+
 /// Type alias to simplify conversions where necessary
 pub type BoxedError = Box<dyn std::error::Error>;
 
@@ -13,10 +15,20 @@ pub enum Error {
     NoResponse,
     /// The requested resource (path or object) doesn't exist
     NotFound(String),
+    /// The requested resource exists but doesn't support the method, which are listed
+    MethodNotAllowed(Vec<Method>),
     /// Incoming request is malformed or incoherent with the server's expectations
     BadRequest(String),
+    /// A field of the incoming request (path parameter, body field...) can't be parsed
+    InvalidField { field: String, message: String },
+    /// The request is valid, but conflicts with the current state of the resource
+    Conflict(String),
+    /// The order contains items that are not on the menu or not available, which are listed
+    UnavailableItems(Vec<String>),
     /// Something went wrong server-side
     InternalServerError(String),
+    /// The server is temporarily unable to handle the request, e.g. out of database connections
+    ServiceUnavailable(String),
 }
 
 impl std::fmt::Display for Error {
@@ -24,8 +36,23 @@ impl std::fmt::Display for Error {
         match self {
             Error::NoResponse => write!(f, "No response from server"),
             Error::NotFound(err) => write!(f, "Not found: {}", err),
+            Error::MethodNotAllowed(allowed) => {
+                write!(
+                    f,
+                    "Method not allowed, expected one of: {}",
+                    allow_header(allowed)
+                )
+            }
             Error::BadRequest(err) => write!(f, "Bad Request: {}", err),
+            Error::InvalidField { field, message } => {
+                write!(f, "Bad Request: invalid '{}': {}", field, message)
+            }
+            Error::Conflict(err) => write!(f, "Conflict: {}", err),
+            Error::UnavailableItems(items) => {
+                write!(f, "Not on the menu or unavailable: {}", items.join(", "))
+            }
             Error::InternalServerError(err) => write!(f, "InternalServerError: {}", err),
+            Error::ServiceUnavailable(err) => write!(f, "Service unavailable: {}", err),
         }
     }
 }
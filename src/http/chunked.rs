@@ -0,0 +1,250 @@
+//! Chunked transfer-encoding, as described in RFC 9112 section 7.1
+//!
+//! The decoder is used by the parsers when a message announces `Transfer-Encoding: chunked`,
+//! the encoder by the server to send streamed response bodies.
+use super::parser::{Limits, ParseError};
+use super::HeaderMap;
+use std::io::{BufRead, Read, Write};
+
+/// Read a single CRLF-terminated line, without the line terminator
+///
+/// Returns the line along with the number of bytes read, terminator included. Lines longer than
+/// `max_len` are rejected, there is no legitimate reason for a chunk header or a trailer to be
+/// bigger than the head of the message.
+fn read_line<R: BufRead>(reader: &mut R, max_len: usize) -> Result<(String, usize), ParseError> {
+    let mut line = Vec::new();
+    // Leave room for the CRLF on top of the content
+    reader
+        .take((max_len as u64).saturating_add(2))
+        .read_until(b'\n', &mut line)
+        .map_err(ParseError::Io)?;
+
+    if line.last() != Some(&b'\n') {
+        return Err(if line.len() > max_len {
+            ParseError::TooLarge
+        } else {
+            ParseError::Incomplete
+        });
+    }
+    let read = line.len();
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    // A bare LF leaves room for one more byte of content than the CRLF we allowed for
+    if line.len() > max_len {
+        return Err(ParseError::TooLarge);
+    }
+
+    let line = String::from_utf8(line).map_err(|err| ParseError::Malformed(err.to_string()))?;
+    Ok((line, read))
+}
+
+/// Consume the CRLF terminating the data of a chunk
+fn expect_crlf<R: BufRead>(reader: &mut R) -> Result<(), ParseError> {
+    let mut crlf = [0; 2];
+    reader
+        .read_exact(&mut crlf)
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => ParseError::Incomplete,
+            _ => ParseError::Io(err),
+        })?;
+
+    if &crlf == b"\r\n" {
+        Ok(())
+    } else {
+        Err(ParseError::Malformed(
+            "Missing CRLF after chunk".to_string(),
+        ))
+    }
+}
+
+/// Parse the size at the start of a chunk header, ignoring chunk extensions
+fn parse_chunk_size(line: &str) -> Result<usize, ParseError> {
+    let size = line.split(';').next().unwrap_or("").trim();
+    usize::from_str_radix(size, 16)
+        .map_err(|err| ParseError::Malformed(format!("Invalid chunk size '{}': {}", size, err)))
+}
+
+/// Read a chunked body from the stream
+///
+/// Returns the decoded body and the trailer fields following the last chunk. The size of the
+/// decoded body is checked against the limits as it is read, not only once it is complete.
+pub(crate) fn read_chunked_body<R: BufRead>(
+    reader: &mut R,
+    limits: &Limits,
+) -> Result<(Vec<u8>, HeaderMap), ParseError> {
+    let mut body = Vec::new();
+
+    loop {
+        let size = parse_chunk_size(&read_line(reader, limits.max_head_size)?.0)?;
+        if size == 0 {
+            break;
+        }
+        if size > limits.max_body_size - body.len() {
+            return Err(ParseError::TooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::UnexpectedEof => ParseError::Incomplete,
+                _ => ParseError::Io(err),
+            })?;
+
+        expect_crlf(reader)?;
+    }
+
+    let mut trailers = HeaderMap::new();
+    // Counts the raw bytes of the trailers, line terminators included
+    let mut trailers_size = 0;
+    loop {
+        let (line, read) = read_line(reader, limits.max_head_size - trailers_size)?;
+        if line.is_empty() {
+            break;
+        }
+        trailers_size += read;
+        if trailers_size > limits.max_head_size {
+            return Err(ParseError::TooLarge);
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| ParseError::Malformed(format!("Invalid trailer '{}'", line)))?;
+        trailers.append(name.trim(), value.trim());
+    }
+
+    Ok((body, trailers))
+}
+
+/// Writer encoding everything written to it as chunks
+///
+/// Each call to `write` produces one chunk, wrap it in a BufWriter to avoid sending lots of
+/// tiny chunks. `finish` must be called to send the last chunk, otherwise the client has no
+/// way of knowing that the body is complete.
+pub(crate) struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Create a new writer sending chunks to `inner`
+    pub(crate) fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+
+    /// Send the last (empty) chunk, without trailers
+    pub(crate) fn finish(mut self) -> std::io::Result<()> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // An empty chunk would end the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:X}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_read_chunked_body() {
+        let input = concat!(
+            "4\r\nWiki\r\n7;ext=value\r\npedia i\r\nB\r\nn \r\nchunks.\r\n0\r\n",
+            "Expires: never\r\n\r\nNEXT"
+        );
+        let mut reader = BufReader::new(input.as_bytes());
+
+        let (body, trailers) = read_chunked_body(&mut reader, &Limits::default()).unwrap();
+
+        assert_eq!(body, b"Wikipedia in \r\nchunks.");
+        assert_eq!(trailers.get("expires"), Some("never"));
+
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "NEXT");
+    }
+
+    #[test]
+    fn test_trailers_over_limit() {
+        let limits = Limits {
+            max_head_size: 16,
+            max_body_size: 8,
+        };
+
+        // A bare LF makes the line one byte longer than the CRLF budget allows
+        let input = format!("0\r\na:{}\nb: c\r\n\r\n", "x".repeat(15));
+        let result = read_chunked_body(&mut BufReader::new(input.as_bytes()), &limits);
+        assert!(matches!(result, Err(ParseError::TooLarge)));
+
+        // The terminators count towards the limit
+        let input = "0\r\na: 1\r\nb: 2\r\nc: 3\r\n\r\n";
+        let result = read_chunked_body(&mut BufReader::new(input.as_bytes()), &limits);
+        assert!(matches!(result, Err(ParseError::TooLarge)));
+
+        let input = "0\r\na: 1\r\nb: 2\r\n\r\n";
+        let (_, trailers) =
+            read_chunked_body(&mut BufReader::new(input.as_bytes()), &limits).unwrap();
+        assert_eq!(trailers.len(), 2);
+    }
+
+    #[test]
+    fn test_read_chunked_body_errors() {
+        let limits = Limits {
+            max_head_size: 64,
+            max_body_size: 8,
+        };
+
+        let input = b"5\r\nHello\r\n5\r\nWorld\r\n0\r\n\r\n";
+        let result = read_chunked_body(&mut BufReader::new(&input[..]), &limits);
+        assert!(matches!(result, Err(ParseError::TooLarge)));
+
+        let input = b"5\r\nHello";
+        let result = read_chunked_body(&mut BufReader::new(&input[..]), &limits);
+        assert!(matches!(result, Err(ParseError::Incomplete)));
+
+        let input = b"zz\r\nHello\r\n0\r\n\r\n";
+        let result = read_chunked_body(&mut BufReader::new(&input[..]), &limits);
+        assert!(matches!(result, Err(ParseError::Malformed(_))));
+
+        let input = b"5\r\nHelloWorld\r\n0\r\n\r\n";
+        let result = read_chunked_body(&mut BufReader::new(&input[..]), &limits);
+        assert!(matches!(result, Err(ParseError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_chunked_writer() {
+        let mut output = Vec::new();
+        let mut writer = ChunkedWriter::new(&mut output);
+        writer.write_all(b"Hello, ").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(b"world! This is long").unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            "7\r\nHello, \r\n13\r\nworld! This is long\r\n0\r\n\r\n"
+        );
+
+        let (body, trailers) =
+            read_chunked_body(&mut BufReader::new(&output[..]), &Limits::default()).unwrap();
+        assert_eq!(body, b"Hello, world! This is long");
+        assert!(trailers.is_empty());
+    }
+}
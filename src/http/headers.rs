@@ -0,0 +1,281 @@
+//! Collection of HTTP header fields
+use std::str::FromStr;
+
+/// Headers of an HTTP message
+///
+/// Header names are case-insensitive: the map keeps them as they were given, but ignores the
+/// case for lookups. A name may appear several times, in which case all the values are kept in
+/// the order they were added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap(Vec<(String, String)>);
+
+/// Error raised when a header can't be interpreted or can't be safely sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHeader(pub String);
+
+impl std::fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid header: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidHeader {}
+
+/// Value of the Connection header, as far as persistent connections are concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connection {
+    KeepAlive,
+    Close,
+}
+
+/// Check that a header name is a valid token (RFC 9110 section 5.6.2)
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c))
+}
+
+/// Check that a header value can't be used to smuggle extra lines into the message
+fn is_valid_value(value: &str) -> bool {
+    !value
+        .bytes()
+        .any(|c| c == b'\r' || c == b'\n' || c == b'\0')
+}
+
+impl HeaderMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        HeaderMap(Vec::new())
+    }
+
+    /// First value associated with the name, if any
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// All the values associated with the name, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Check whether there is at least one value associated with the name
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Set the value of a header, replacing all the values it previously had
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.0.push((name, value.into()));
+    }
+
+    /// Add a value to a header, keeping the values it previously had
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.0.push((name.into(), value.into()));
+    }
+
+    /// Remove all the values associated with the name
+    ///
+    /// Returns true if there was at least one.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.0.len();
+        self.0.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        len != self.0.len()
+    }
+
+    /// Number of values in the map
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check whether the map has no values
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the (name, value) pairs in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Length of the body as announced by Content-Length
+    ///
+    /// The header may be repeated as long as all the values agree, anything else is an error
+    /// since we can't know where the body ends.
+    pub fn content_length(&self) -> Result<Option<usize>, InvalidHeader> {
+        let mut length = None;
+        for value in self.get_all("Content-Length") {
+            let parsed = usize::from_str(value.trim())
+                .map_err(|err| InvalidHeader(format!("Content-Length '{}': {}", value, err)))?;
+            if length.is_some_and(|length| length != parsed) {
+                return Err(InvalidHeader(
+                    "Conflicting Content-Length values".to_string(),
+                ));
+            }
+            length = Some(parsed);
+        }
+        Ok(length)
+    }
+
+    /// Media type of the body, without its parameters
+    pub fn content_type(&self) -> Option<&str> {
+        self.get("Content-Type")
+            .map(|value| value.split(';').next().unwrap_or("").trim())
+    }
+
+    /// Whether the sender wants the connection to be kept open or closed
+    ///
+    /// Returns None if the header is missing or doesn't say, in which case the default of the
+    /// protocol version applies.
+    pub fn connection(&self) -> Option<Connection> {
+        let options = self
+            .get_all("Connection")
+            .flat_map(|value| value.split(','))
+            .map(str::trim);
+
+        let mut result = None;
+        for option in options {
+            if option.eq_ignore_ascii_case("close") {
+                return Some(Connection::Close);
+            } else if option.eq_ignore_ascii_case("keep-alive") {
+                result = Some(Connection::KeepAlive);
+            }
+        }
+        result
+    }
+
+    /// Check that every header can be written to the wire as is
+    ///
+    /// Names must be valid tokens, and values can't contain line breaks. Otherwise a value
+    /// coming from user input could be used to inject headers or even a whole response.
+    pub fn validate(&self) -> Result<(), InvalidHeader> {
+        for (name, value) in self.iter() {
+            if !is_valid_name(name) {
+                return Err(InvalidHeader(format!("'{}' is not a valid name", name)));
+            }
+            if !is_valid_value(value) {
+                return Err(InvalidHeader(format!("Invalid value for '{}'", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HeaderMap {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        HeaderMap(
+            iter.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> Extend<(K, V)> for HeaderMap {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive_lookup() {
+        let mut headers = HeaderMap::new();
+        headers.append("Content-Type", "application/json; charset=utf-8");
+        headers.append("accept", "text/plain");
+        headers.append("ACCEPT", "application/json");
+
+        assert_eq!(
+            headers.get("content-type"),
+            Some("application/json; charset=utf-8")
+        );
+        assert_eq!(headers.content_type(), Some("application/json"));
+        assert_eq!(
+            headers.get_all("Accept").collect::<Vec<_>>(),
+            vec!["text/plain", "application/json"]
+        );
+        assert!(!headers.contains("Content-Length"));
+
+        headers.insert("Accept", "*/*");
+        assert_eq!(headers.get_all("accept").collect::<Vec<_>>(), vec!["*/*"]);
+        assert_eq!(headers.len(), 2);
+
+        assert!(headers.remove("CONTENT-TYPE"));
+        assert!(!headers.remove("Content-Type"));
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn test_content_length() {
+        let headers: HeaderMap = [("content-length", "42")].into_iter().collect();
+        assert_eq!(headers.content_length(), Ok(Some(42)));
+
+        let headers: HeaderMap = [("Content-Length", "42"), ("Content-Length", "42")]
+            .into_iter()
+            .collect();
+        assert_eq!(headers.content_length(), Ok(Some(42)));
+
+        let headers: HeaderMap = [("Content-Length", "42"), ("Content-Length", "43")]
+            .into_iter()
+            .collect();
+        assert!(headers.content_length().is_err());
+
+        let headers: HeaderMap = [("Content-Length", "-1")].into_iter().collect();
+        assert!(headers.content_length().is_err());
+
+        assert_eq!(HeaderMap::new().content_length(), Ok(None));
+    }
+
+    #[test]
+    fn test_connection() {
+        let headers: HeaderMap = [("connection", "Keep-Alive")].into_iter().collect();
+        assert_eq!(headers.connection(), Some(Connection::KeepAlive));
+
+        let headers: HeaderMap = [("Connection", "keep-alive, close")].into_iter().collect();
+        assert_eq!(headers.connection(), Some(Connection::Close));
+
+        let headers: HeaderMap = [("Connection", "upgrade")].into_iter().collect();
+        assert_eq!(headers.connection(), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let headers: HeaderMap = [("X-Custom", "value; with=params")].into_iter().collect();
+        assert!(headers.validate().is_ok());
+
+        let headers: HeaderMap = [("X-Custom", "value\r\nSet-Cookie: evil")]
+            .into_iter()
+            .collect();
+        assert!(headers.validate().is_err());
+
+        let headers: HeaderMap = [("X-Custom: evil\r\nX", "value")].into_iter().collect();
+        assert!(headers.validate().is_err());
+
+        let headers: HeaderMap = [("", "value")].into_iter().collect();
+        assert!(headers.validate().is_err());
+    }
+}
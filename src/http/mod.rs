@@ -0,0 +1,946 @@
+use crate::{errors, threadpool::ThreadPool};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+mod chunked;
+mod headers;
+mod method;
+mod parser;
+mod query;
+mod status;
+
+use chunked::ChunkedWriter;
+pub use headers::{Connection, HeaderMap, InvalidHeader};
+pub use method::{Method, UnknownMethod};
+use parser::parse_request;
+pub use parser::{parse_response, parse_response_to, Limits, ParseError};
+pub(crate) use query::decode_path_segment;
+use query::split_target;
+pub use query::Query;
+pub use status::StatusCode;
+
+/// Version of the HTTP protocol a request was sent with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Version {
+    /// HTTP/1.0, where connections are closed after each request unless asked otherwise
+    Http10,
+    /// HTTP/1.1, where connections are kept open unless asked otherwise
+    #[default]
+    Http11,
+}
+
+impl Version {
+    /// Whether the connection stays open after a request sending the given Connection header
+    pub fn keeps_alive(self, connection: Option<Connection>) -> bool {
+        match self {
+            Version::Http10 => connection == Some(Connection::KeepAlive),
+            Version::Http11 => connection != Some(Connection::Close),
+        }
+    }
+}
+
+/// Represents an HTTP request.
+///
+/// This datastructure probably needs to be simplified/split to avoid carrying redundant
+/// information around the application (typically path + params after rounting).
+#[derive(Debug)]
+pub struct Request {
+    /// The HTTP method used in the request
+    pub method: Method,
+    /// Version of the protocol, HTTP/1.1 for requests that weren't received from the network
+    pub version: Version,
+    /// Path of the request without the query string, still percent-encoded (the path
+    /// parameters are decoded by the router)
+    pub path: String,
+    /// Parameters of the query string
+    pub query: Query,
+    /// Headers of the request
+    pub headers: HeaderMap,
+    /// Body of the request, as received
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Create a new request from scratch
+    ///
+    /// The target is split between path and query the same way it would be for a request
+    /// received by the server.
+    pub fn new(
+        method: Method,
+        target: &str,
+        headers: HeaderMap,
+        body: impl Into<Vec<u8>>,
+    ) -> Request {
+        let (path, query) = split_target(target);
+        Request {
+            method,
+            version: Version::default(),
+            path,
+            query,
+            headers,
+            body: body.into(),
+        }
+    }
+    /// Create a new GET request for the given path, with an empty body
+    pub fn get(path: &str) -> Request {
+        Request::new(Method::Get, path, HeaderMap::new(), Vec::new())
+    }
+    /// Create a new POST request for the given path, with the given body
+    pub fn post(path: &str, body: impl Into<Vec<u8>>) -> Request {
+        Request::new(Method::Post, path, HeaderMap::new(), body)
+    }
+    /// Create a new DELETE request for the given path, with the given body
+    pub fn delete(path: &str, body: impl Into<Vec<u8>>) -> Request {
+        Request::new(Method::Delete, path, HeaderMap::new(), body)
+    }
+    /// Create a new PUT request for the given path, with the given body
+    pub fn put(path: &str, body: impl Into<Vec<u8>>) -> Request {
+        Request::new(Method::Put, path, HeaderMap::new(), body)
+    }
+    /// Create a new PATCH request for the given path, with the given body
+    pub fn patch(path: &str, body: impl Into<Vec<u8>>) -> Request {
+        Request::new(Method::Patch, path, HeaderMap::new(), body)
+    }
+
+    /// Body of the request as UTF-8 text
+    ///
+    /// Fails with a BadRequest if the body is not valid UTF-8.
+    pub fn text(&self) -> errors::Result<&str> {
+        std::str::from_utf8(&self.body)
+            .map_err(|err| errors::Error::BadRequest(err.to_string()).into())
+    }
+
+    /// Deserialize the JSON body of the request
+    ///
+    /// Fails with an InvalidField naming the faulty field if the body is not a valid JSON
+    /// representation of T, or with a BadRequest if the problem isn't specific to a field (e.g.
+    /// the body is not valid JSON at all).
+    pub fn json<T: DeserializeOwned>(&self) -> errors::Result<T> {
+        let deserializer = &mut serde_json::Deserializer::from_slice(&self.body);
+        serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            let field = err.path().to_string();
+            if field == "." {
+                errors::Error::BadRequest(format!("Invalid JSON body: {}", err.inner())).into()
+            } else {
+                errors::Error::InvalidField {
+                    field,
+                    message: err.inner().to_string(),
+                }
+                .into()
+            }
+        })
+    }
+}
+
+/// Producer of a streamed response body
+///
+/// The function is given a writer once the head of the response has been sent, and everything
+/// written to it goes to the client as it is produced. This allows sending large bodies without
+/// holding them in memory. Errors can't be reported to the client at this point, the connection
+/// is simply closed.
+pub struct BodyStream(BodyProducer);
+
+/// Function writing a streamed body
+type BodyProducer = Box<dyn FnOnce(&mut dyn Write) -> std::io::Result<()> + Send>;
+
+impl std::fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BodyStream")
+    }
+}
+
+/// An HTTP response to be sent to a client
+#[derive(Debug)]
+pub struct Response {
+    /// Status code of the response
+    pub status: StatusCode,
+    /// Headers for the response. Content-Length, Transfer-Encoding and Connection are managed
+    /// by the server and ignored if present, they are added automatically on serialization.
+    pub headers: HeaderMap,
+    /// Body of the response. Leave empty for an empty body
+    pub body: Vec<u8>,
+    /// Streamed body of the response. If set, it is sent with the chunked transfer-encoding
+    /// instead of `body`.
+    pub stream: Option<BodyStream>,
+}
+
+impl Response {
+    /// Creates an empty OK response (204)
+    pub fn ok() -> Response {
+        Response {
+            status: StatusCode::NO_CONTENT,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            stream: None,
+        }
+    }
+
+    /// Creates an OK (200) response with the given body
+    pub fn ok_with_body(body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: body.into(),
+            stream: None,
+        }
+    }
+
+    /// Creates an OK (200) response with the JSON representation of the given value as body
+    ///
+    /// Returns an InternalServerError if the serialization fails, as this would be a programming
+    /// error.
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> errors::Result<Response> {
+        let body = serde_json::to_vec(value)
+            .map_err(|err| errors::Error::InternalServerError(err.to_string()))?;
+
+        let mut response = Response::ok_with_body(body);
+        response.headers.insert("Content-Type", "application/json");
+        Ok(response)
+    }
+
+    /// Creates an OK (200) response whose body is written by the given function
+    ///
+    /// The body is sent as it is written, see BodyStream.
+    pub fn streaming<F>(producer: F) -> Response
+    where
+        F: FnOnce(&mut dyn Write) -> std::io::Result<()> + Send + 'static,
+    {
+        Response {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            stream: Some(BodyStream(Box::new(producer))),
+        }
+    }
+
+    /// Creates an error response with the given body.
+    ///
+    /// The code must be in the 4xx or 5xx range.
+    ///
+    /// No body is added intentionally to avoid leaking information about the server until I build
+    /// some better error handling.
+    pub fn error(code: StatusCode) -> Response {
+        assert!(
+            code.is_client_error() || code.is_server_error(),
+            "Invalid error code"
+        );
+        Response {
+            status: code,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            stream: None,
+        }
+    }
+
+    /// Creates a Method Not Allowed (405) response, listing the allowed methods in the Allow
+    /// header
+    pub fn method_not_allowed(allowed: &[Method]) -> Response {
+        let mut response = Self::error(StatusCode::METHOD_NOT_ALLOWED);
+        response.headers.insert("Allow", allow_header(allowed));
+        response
+    }
+
+    /// Creates an Internal Server Error (500) response.
+    pub fn internal_server_error() -> Response {
+        Self::error(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Creates a Service Unavailable (503) response, asking the client to retry a bit later
+    pub fn service_unavailable() -> Response {
+        let mut response = Self::error(StatusCode::SERVICE_UNAVAILABLE);
+        response
+            .headers
+            .insert("Retry-After", RETRY_AFTER_SECONDS.to_string());
+        response
+    }
+
+    /// Body of the response as UTF-8 text
+    pub fn text(&self) -> errors::Result<&str> {
+        Ok(std::str::from_utf8(&self.body)?)
+    }
+
+    /// Deserialize the JSON body of the response
+    pub fn json_body<T: DeserializeOwned>(&self) -> errors::Result<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// Seconds a client is asked to wait before retrying a request the server couldn't handle
+const RETRY_AFTER_SECONDS: u32 = 1;
+
+/// Value of the Allow header for the given methods
+pub fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Headers describing the framing of the response, which only the server can decide
+const MANAGED_HEADERS: [&str; 3] = ["Content-Length", "Transfer-Encoding", "Connection"];
+
+/// Writes an HTTP response to a stream
+///
+/// The Connection header is added automatically to let the client know whether we'll keep
+/// listening on this connection after this response. Regular bodies are sent with their
+/// Content-Length, streamed bodies in chunks. HTTP/1.0 clients don't know about chunks
+/// (RFC 9112 §6.1), streamed bodies are sent to them as is and delimited by closing the
+/// connection, whatever `keep_alive` says.
+///
+/// A response with headers that can't be safely written is replaced with a 500, rather than
+/// letting a handler inject arbitrary content in the stream.
+///
+/// Responses to HEAD requests (`head_only`) are sent with the framing headers their body would
+/// have had, but without the body itself.
+fn respond<W: Write>(
+    stream: &mut W,
+    resp: Response,
+    keep_alive: bool,
+    head_only: bool,
+    version: Version,
+) -> std::io::Result<()> {
+    let resp = match resp.headers.validate() {
+        Ok(()) => resp,
+        Err(err) => {
+            eprintln!("Refusing to send response: {}", err);
+            Response::internal_server_error()
+        }
+    };
+
+    let close_delimited = resp.stream.is_some() && version == Version::Http10;
+    let keep_alive = keep_alive && !close_delimited;
+    let framing = match resp.stream {
+        Some(_) if close_delimited => String::new(),
+        Some(_) => "Transfer-Encoding: chunked\r\n".to_string(),
+        None => format!("Content-Length: {}\r\n", resp.body.len()),
+    };
+
+    let mut message = format!(
+        "HTTP/1.1 {}\r\n{}Connection: {}\r\n{}\r\n",
+        resp.status,
+        framing,
+        if keep_alive { "keep-alive" } else { "close" },
+        resp.headers
+            .iter()
+            .filter(|(k, _)| !MANAGED_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(k)))
+            .map(|(k, v)| format!["{}: {}\r\n", k, v])
+            .collect::<Vec<_>>()
+            .join(""),
+    )
+    .into_bytes();
+    if head_only {
+        stream.write_all(&message)?;
+        return stream.flush();
+    }
+    // Head and body are sent in one go to avoid the delays of Nagle's algorithm
+    message.extend_from_slice(&resp.body);
+    stream.write_all(&message)?;
+
+    match resp.stream {
+        Some(BodyStream(producer)) if close_delimited => {
+            let mut writer = BufWriter::new(&mut *stream);
+            producer(&mut writer)?;
+            writer.flush()
+        }
+        Some(BodyStream(producer)) => {
+            let mut writer = BufWriter::new(ChunkedWriter::new(&mut *stream));
+            producer(&mut writer)?;
+            writer
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .finish()
+        }
+        None => stream.flush(),
+    }
+}
+
+/// Settings for persistent connections
+///
+/// Each open connection holds a worker of the threadpool for as long as it waits for a request,
+/// and the pool has only as many workers as the machine has cores. A client keeping its
+/// connection open without using it blocks a worker that could serve a new connection, so the
+/// wait between two requests is much shorter than the wait for the first one. Clients sending
+/// their requests back to back (scripts, pipelining) save a connection per request, while the
+/// ones pausing for longer than `idle_timeout` have to reconnect, which is cheap compared to
+/// starving the server.
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAlive {
+    /// How long to wait for the first request of a connection before closing it
+    pub request_timeout: Duration,
+    /// How long to wait for the next request once one was answered before closing the
+    /// connection
+    pub idle_timeout: Duration,
+    /// Maximum number of requests served on a single connection before closing it
+    pub max_requests: usize,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive {
+            request_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_millis(200),
+            max_requests: 100,
+        }
+    }
+}
+
+/// This is the main server.
+///
+/// It listens for incomming connections on a TCP socket, parses the requests and dispatches them
+/// to a handler. Whatever the handler produces is then converted in an HTTP response and sent
+/// back to the client.
+pub struct HttpServer {
+    listener: TcpListener,
+    limits: Limits,
+    keep_alive: KeepAlive,
+}
+
+/// Serve the requests coming from a single connection until it is closed
+///
+/// Requests are answered in order. The connection is closed after `max_requests` requests, when
+/// the client or the handler asks for it with `Connection: close`, or on the first error.
+/// HTTP/1.0 clients must ask for the connection to be kept open with `Connection: keep-alive`.
+/// Bytes following a request are kept in the reader, so pipelined requests are not lost.
+///
+/// `on_idle` is called after each response when the connection is kept open, before waiting
+/// for the next request. The connection is closed if it fails.
+fn serve_connection<R, W, I, F>(
+    reader: &mut R,
+    writer: &mut W,
+    limits: &Limits,
+    max_requests: usize,
+    mut on_idle: I,
+    handler: F,
+) where
+    R: BufRead,
+    W: Write,
+    I: FnMut() -> std::io::Result<()>,
+    F: Fn(Request) -> Response,
+{
+    for served in 1..=max_requests {
+        let request = match parse_request(reader, limits) {
+            Ok(request) => request,
+            // Nothing to answer to, the client is gone or was idle for too long
+            Err(ParseError::ConnectionClosed) | Err(ParseError::Io(_)) => return,
+            Err(err) => {
+                let response = match err {
+                    ParseError::TooLarge => Response::error(StatusCode::PAYLOAD_TOO_LARGE),
+                    ParseError::UnknownMethod(_) => Response::error(StatusCode::NOT_IMPLEMENTED),
+                    err => {
+                        eprintln!("Failed to parse request: {}", err);
+                        Response::error(StatusCode::BAD_REQUEST)
+                    }
+                };
+                // We're closing anyway, there's nothing to do if this fails
+                let _ = respond(writer, response, false, false, Version::default());
+                return;
+            }
+        };
+
+        let version = request.version;
+        let client_keep_alive = version.keeps_alive(request.headers.connection());
+        let head_only = request.method == Method::Head;
+        let response = handler(request);
+        // Without chunks, the end of a streamed body can only be told by closing
+        let close_delimited = response.stream.is_some() && version == Version::Http10;
+        let keep_alive = served < max_requests
+            && client_keep_alive
+            && !close_delimited
+            && response.headers.connection() != Some(Connection::Close);
+
+        if let Err(err) = respond(writer, response, keep_alive, head_only, version) {
+            eprintln!("Failed to respond {}", err);
+            return;
+        }
+        if !keep_alive {
+            return;
+        }
+        if let Err(err) = on_idle() {
+            eprintln!("Failed to keep the connection open: {}", err);
+            return;
+        }
+    }
+}
+
+/// Serve the requests coming from a TCP stream, closing idle connections after the timeouts
+fn handle_stream<F>(stream: TcpStream, limits: &Limits, keep_alive: &KeepAlive, handler: F)
+where
+    F: Fn(Request) -> Response,
+{
+    if let Err(err) = stream.set_read_timeout(Some(keep_alive.request_timeout)) {
+        eprintln!("Failed to set the connection timeout: {}", err);
+        return;
+    }
+
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+    serve_connection(
+        &mut reader,
+        &mut writer,
+        limits,
+        keep_alive.max_requests,
+        || stream.set_read_timeout(Some(keep_alive.idle_timeout)),
+        handler,
+    );
+}
+
+impl HttpServer {
+    /// Create a new server listening on the given address
+    pub fn new(addr: &str) -> errors::Result<Self> {
+        Ok(HttpServer {
+            listener: TcpListener::bind(addr)?,
+            limits: Limits::default(),
+            keep_alive: KeepAlive::default(),
+        })
+    }
+
+    /// Replace the size limits applied to incoming requests
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Replace the settings for persistent connections
+    pub fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Start the server
+    ///
+    /// Calls the handler with the incoming requests. Uses a threadpool internally to handle the
+    /// connections concurrently on as many threads as the system can handle.
+    ///
+    /// This function is blocking, with no real way of stopping it (except the socket being
+    /// forcefully closed by the OS or the program being killed)
+    pub fn serve<F>(&self, handler: F)
+    where
+        F: Fn(Request) -> Response + Send + Sync + 'static + Clone,
+    {
+        let threadpool = ThreadPool::new(
+            std::thread::available_parallelism()
+                .map(|x| x.into())
+                .unwrap_or(4),
+        );
+        for stream in self.listener.incoming() {
+            let stream = stream.unwrap();
+            let handler = handler.clone();
+            let limits = self.limits;
+            let keep_alive = self.keep_alive;
+            threadpool.execute(move || handle_stream(stream, &limits, &keep_alive, &handler))
+        }
+    }
+
+    /// Utility function for one-shot servers.
+    ///
+    /// This is mostly for testing, it listens to a single connection, processes its requests
+    /// and exit once the connection is closed.
+    pub fn serve_once<F>(&self, handler: F)
+    where
+        F: Fn(Request) -> Response,
+    {
+        let stream = self.listener.incoming().next().unwrap().unwrap();
+        handle_stream(stream, &self.limits, &self.keep_alive, &handler);
+    }
+}
+
+/// Simple HTTP client
+///
+/// It sends HTTP requests from a set of parameters, then parses and yields the server response.
+/// The connection is kept open between requests, so several requests can be sent with the same
+/// client as long as the server doesn't close it.
+pub struct HttpClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    limits: Limits,
+}
+
+impl HttpClient {
+    /// Create a new client connected to the given server.
+    ///
+    /// An error is returned if the connection cannot be made for whatever reason
+    pub fn new(server: &str) -> errors::Result<Self> {
+        let stream = TcpStream::connect(server)?;
+        // The reader must outlive individual requests to keep whatever it buffered past the
+        // end of a response
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(HttpClient {
+            stream,
+            reader,
+            limits: Limits::unbounded_body(),
+        })
+    }
+
+    /// Replace the size limits applied to the responses
+    ///
+    /// By default, the client accepts bodies of any size.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Send an HTTP request on the open connection.
+    ///
+    /// If the server answered a previous request with `Connection: close`, this fails with
+    /// Error::NoResponse and a new client must be created.
+    ///
+    /// Responses to HEAD requests have an empty body, whatever their headers say.
+    pub fn send(
+        &mut self,
+        method: Method,
+        endpoint: &str,
+        body: impl AsRef<[u8]>,
+    ) -> errors::Result<Response> {
+        let body = body.as_ref();
+        let mut message = format! {
+            "{} {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            method, endpoint, body.len()
+        }
+        .into_bytes();
+        message.extend_from_slice(body);
+        self.stream.write_all(&message)?;
+
+        parse_response_to(&mut self.reader, method, &self.limits).map_err(|err| match err {
+            ParseError::ConnectionClosed => errors::Error::NoResponse.into(),
+            err => err.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_simple_http_request() {
+        // I normally would not do this kind of tests here but time is short
+        // It may fail if started several times in a row since the OS may takes some time
+        // to make the port available again (or if it is already in use by something else)
+        // . If I get around to it I'll extract this into something cleaner
+        static ADDR: &str = "127.0.0.1:18422";
+
+        let handle = std::thread::spawn(|| {
+            eprintln!("Connecting to {}", ADDR);
+            let server = HttpServer::new(ADDR);
+            match server {
+                Ok(s) => s.serve_once(|_| Response::ok()),
+                Err(err) => eprintln!("Failed to spawn server: {}", err),
+            }
+        });
+
+        let mut client = (|| {
+            for _ in 1..10 {
+                match HttpClient::new(ADDR) {
+                    Ok(c) => return Some(c),
+                    Err(err) => {
+                        eprintln!("Trying to connect to {}: {}", ADDR, err);
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                    }
+                }
+            }
+            None
+        })()
+        .expect("Failed to connect client");
+
+        // Several requests on the same connection
+        for _ in 0..3 {
+            let resp = client
+                .send(Method::Post, "/", "{\"content\": \"Hello\"}")
+                .expect("Failed to communicate with server");
+
+            assert_eq!(resp.status, StatusCode::NO_CONTENT);
+        }
+
+        // The server only stops once the connection is closed
+        drop(client);
+        handle.join().unwrap();
+    }
+
+    /// Run the given raw requests through `serve_connection` and return the raw output
+    fn serve_raw(input: &str, max_requests: usize) -> String {
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut output = Vec::new();
+        serve_connection(
+            &mut reader,
+            &mut output,
+            &Limits::default(),
+            max_requests,
+            || Ok(()),
+            |req| Response::ok_with_body(req.path),
+        );
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Run the given raw requests through `serve_connection` with a custom handler
+    fn serve_raw_with<F: Fn(Request) -> Response>(input: &str, handler: F) -> String {
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut output = Vec::new();
+        serve_connection(
+            &mut reader,
+            &mut output,
+            &Limits::default(),
+            10,
+            || Ok(()),
+            handler,
+        );
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_pipelined_requests() {
+        let output = serve_raw(
+            "GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n",
+            10,
+        );
+
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 2);
+        assert_eq!(output.matches("Connection: keep-alive").count(), 2);
+        assert!(output.find("/first").unwrap() < output.find("/second").unwrap());
+    }
+
+    #[test]
+    fn test_connection_close() {
+        let output = serve_raw(
+            "GET /first HTTP/1.1\r\nConnection: close\r\n\r\nGET /second HTTP/1.1\r\n\r\n",
+            10,
+        );
+
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+        assert!(output.contains("Connection: close"));
+        assert!(!output.contains("/second"));
+    }
+
+    #[test]
+    fn test_http_1_0_connections() {
+        // Closed by default
+        let output = serve_raw(
+            "GET /first HTTP/1.0\r\n\r\nGET /second HTTP/1.0\r\n\r\n",
+            10,
+        );
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+        assert!(output.contains("Connection: close"));
+        assert!(!output.contains("/second"));
+
+        // Kept open on demand
+        let output = serve_raw(
+            "GET /first HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /second HTTP/1.0\r\n\r\n",
+            10,
+        );
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 2);
+        assert_eq!(output.matches("Connection: keep-alive").count(), 1);
+        assert_eq!(output.matches("Connection: close").count(), 1);
+    }
+
+    #[test]
+    fn test_idle_connection_is_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let keep_alive = KeepAlive {
+            request_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_millis(50),
+            max_requests: 100,
+        };
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_stream(stream, &Limits::default(), &keep_alive, |_| Response::ok());
+        });
+
+        let mut client = HttpClient::new(&addr.to_string()).unwrap();
+        let response = client.send(Method::Get, "/", "").unwrap();
+        assert_eq!(response.headers.connection(), Some(Connection::KeepAlive));
+
+        // The worker is given back long before the timeout of the first request
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(server.is_finished());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_max_requests_per_connection() {
+        let output = serve_raw(
+            "GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\nGET /3 HTTP/1.1\r\n\r\n",
+            2,
+        );
+
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 2);
+        assert_eq!(output.matches("Connection: keep-alive").count(), 1);
+        assert_eq!(output.matches("Connection: close").count(), 1);
+    }
+
+    #[test]
+    fn test_on_idle_between_requests() {
+        let input = "GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\nConnection: close\r\n\r\n";
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut output = Vec::new();
+        let mut idle = 0;
+        let on_idle = || {
+            idle += 1;
+            Ok(())
+        };
+        serve_connection(
+            &mut reader,
+            &mut output,
+            &Limits::default(),
+            10,
+            on_idle,
+            |_| Response::ok(),
+        );
+        assert_eq!(idle, 1);
+
+        // A failing hook closes the connection
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut output = Vec::new();
+        let on_idle = || Err(std::io::Error::other("timeout"));
+        serve_connection(
+            &mut reader,
+            &mut output,
+            &Limits::default(),
+            10,
+            on_idle,
+            |_| Response::ok(),
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.matches("HTTP/1.1 204 No Content").count(), 1);
+    }
+
+    #[test]
+    fn test_streamed_response() {
+        let input = "GET /export HTTP/1.1\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut output = Vec::new();
+        serve_connection(
+            &mut reader,
+            &mut output,
+            &Limits::default(),
+            10,
+            || Ok(()),
+            |req| {
+                if req.path == "/export" {
+                    Response::streaming(|writer| {
+                        for i in 0..1000 {
+                            writeln!(writer, "line {}", i)?;
+                        }
+                        Ok(())
+                    })
+                } else {
+                    Response::ok_with_body(req.path)
+                }
+            },
+        );
+
+        let raw = String::from_utf8(output.clone()).unwrap();
+        assert!(raw.contains("Transfer-Encoding: chunked"));
+
+        let mut reader = BufReader::new(&output[..]);
+        let export = parse_response(&mut reader, &Limits::default()).unwrap();
+        assert_eq!(export.text().unwrap().lines().count(), 1000);
+        assert_eq!(export.text().unwrap().lines().last(), Some("line 999"));
+
+        let next = parse_response(&mut reader, &Limits::default()).unwrap();
+        assert_eq!(next.text().unwrap(), "/next");
+    }
+
+    #[test]
+    fn test_streamed_response_to_http_1_0() {
+        let input = concat!(
+            "GET /export HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+            "GET /next HTTP/1.0\r\n\r\n"
+        );
+        let output = serve_raw_with(input, |_| {
+            Response::streaming(|writer| {
+                for i in 0..3 {
+                    writeln!(writer, "line {}", i)?;
+                }
+                Ok(())
+            })
+        });
+
+        assert!(!output.contains("Transfer-Encoding"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.contains("Connection: close"));
+        assert!(output.ends_with("\r\n\r\nline 0\nline 1\nline 2\n"));
+        // The body ends with the connection, the next request is never served
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+        let export = parse_response(&mut BufReader::new(output.as_bytes()), &Limits::default());
+        assert_eq!(export.unwrap().text().unwrap(), "line 0\nline 1\nline 2\n");
+    }
+
+    #[test]
+    fn test_header_injection() {
+        let output = serve_raw_with("GET / HTTP/1.1\r\n\r\n", |_| {
+            let mut response = Response::ok_with_body("Hello".to_string());
+            response
+                .headers
+                .insert("X-Custom", "value\r\nSet-Cookie: evil=true");
+            response
+        });
+
+        assert!(output.starts_with("HTTP/1.1 500 Internal Server Error"));
+        assert!(!output.contains("Set-Cookie"));
+        assert!(!output.contains("Hello"));
+    }
+
+    #[test]
+    fn test_managed_headers_are_ignored() {
+        let output = serve_raw_with("GET / HTTP/1.1\r\n\r\n", |_| {
+            let mut response = Response::ok_with_body("Hello".to_string());
+            response.headers.insert("content-length", "1000");
+            response.headers.insert("X-Custom", "value");
+            response
+        });
+
+        assert!(output.contains("Content-Length: 5\r\n"));
+        assert!(!output.contains("1000"));
+        assert!(output.contains("X-Custom: value\r\n"));
+    }
+
+    #[test]
+    fn test_head_response() {
+        let input = "HEAD /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\n\r\n";
+        let output = serve_raw(input, 10);
+
+        let mut reader = BufReader::new(output.as_bytes());
+        let head = parse_response_to(&mut reader, Method::Head, &Limits::default()).unwrap();
+        assert_eq!(head.headers.content_length(), Ok(Some("/first".len())));
+        assert!(head.body.is_empty());
+        assert!(!output.contains("/first"));
+
+        let next = parse_response(&mut reader, &Limits::default()).unwrap();
+        assert_eq!(next.text().unwrap(), "/second");
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+        let response = Response::method_not_allowed(&[Method::Get, Method::Head]);
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers.get("allow"), Some("GET, HEAD"));
+    }
+
+    #[test]
+    fn test_json_bodies() {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Payload {
+            name: String,
+        }
+
+        let payload = Payload {
+            name: "Pizza".to_string(),
+        };
+        let response = Response::json(&payload).unwrap();
+        assert_eq!(response.headers.content_type(), Some("application/json"));
+        assert_eq!(response.json_body::<Payload>().unwrap(), payload);
+
+        let request = Request::post("/", response.body);
+        assert_eq!(request.json::<Payload>().unwrap(), payload);
+
+        let request = Request::post("/", "{\"nam\": 1}");
+        let err = request.json::<Payload>().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<errors::Error>(),
+            Some(errors::Error::BadRequest(_))
+        ));
+    }
+}
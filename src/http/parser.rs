@@ -0,0 +1,665 @@
+//! Parsers turning a byte stream into HTTP requests and responses
+//!
+//! Both parsers share the same core: the head of the message is read incrementally until
+//! httparse is able to make sense of it, then the body is read according to the headers. Only
+//! the bytes belonging to the message are consumed from the reader, anything past it stays
+//! buffered for the next message.
+use super::chunked::read_chunked_body;
+use super::{HeaderMap, Method, Request, Response, StatusCode, Version};
+use std::io::{BufRead, Read};
+
+/// Size limits applied when reading an HTTP message from a stream
+///
+/// Messages going over these limits are rejected as a whole rather than being buffered
+/// indefinitely. The server answers them with a 413.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Maximum size of the start line and headers, in bytes
+    pub max_head_size: usize,
+    /// Maximum size of the body, in bytes
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_head_size: 8 * 1024,
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// Limits that never reject a body, whatever its size
+    ///
+    /// The head is still capped to the default size, a server sending endless headers is not
+    /// something we want to wait for.
+    pub fn unbounded_body() -> Self {
+        Limits {
+            max_body_size: usize::MAX,
+            ..Default::default()
+        }
+    }
+}
+
+/// Reasons why an HTTP message couldn't be read from a stream
+#[derive(Debug)]
+pub enum ParseError {
+    /// The stream was closed before any byte of the message was received
+    ConnectionClosed,
+    /// The stream was closed in the middle of the message
+    Incomplete,
+    /// The data received isn't valid HTTP
+    Malformed(String),
+    /// The message is bigger than the configured limits
+    TooLarge,
+    /// The request uses a method we don't know about
+    UnknownMethod(String),
+    /// Reading from the underlying stream failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::ConnectionClosed => write!(f, "Connection closed"),
+            ParseError::Incomplete => write!(f, "Incomplete message"),
+            ParseError::Malformed(err) => write!(f, "Malformed message: {}", err),
+            ParseError::TooLarge => write!(f, "Message too large"),
+            ParseError::UnknownMethod(method) => write!(f, "Unknown method '{}'", method),
+            ParseError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Start line and headers of an HTTP message
+///
+/// This is the only part that differs between requests and responses as far as the parsing is
+/// concerned.
+trait Head: Sized {
+    /// Try to parse the head at the start of the buffer
+    ///
+    /// Returns the length of the head and its content if the buffer contains it entirely,
+    /// None if more data is needed.
+    fn parse(buf: &[u8]) -> Result<Option<(usize, Self)>, ParseError>;
+}
+
+/// Start line and headers of a request
+struct RequestHead {
+    method: Method,
+    version: Version,
+    path: String,
+    headers: HeaderMap,
+}
+
+/// Start line and headers of a response
+struct ResponseHead {
+    status: StatusCode,
+    headers: HeaderMap,
+}
+
+impl Head for RequestHead {
+    fn parse(buf: &[u8]) -> Result<Option<(usize, Self)>, ParseError> {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut req = httparse::Request::new(&mut headers);
+
+        match req.parse(buf) {
+            Ok(httparse::Status::Complete(parsed_len)) => {
+                let method = req.method.unwrap();
+                let method = method
+                    .parse()
+                    .map_err(|_| ParseError::UnknownMethod(method.to_string()))?;
+
+                Ok(Some((
+                    parsed_len,
+                    RequestHead {
+                        method,
+                        version: match req.version {
+                            Some(0) => Version::Http10,
+                            _ => Version::Http11,
+                        },
+                        path: req.path.unwrap().to_string(),
+                        headers: collect_headers(req.headers),
+                    },
+                )))
+            }
+            Ok(httparse::Status::Partial) => Ok(None),
+            Err(err) => Err(ParseError::Malformed(err.to_string())),
+        }
+    }
+}
+
+impl Head for ResponseHead {
+    fn parse(buf: &[u8]) -> Result<Option<(usize, Self)>, ParseError> {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut resp = httparse::Response::new(&mut headers);
+
+        match resp.parse(buf) {
+            Ok(httparse::Status::Complete(parsed_len)) => {
+                // httparse guarantees 3 digits, but not that the first one is meaningful
+                let code = resp.code.unwrap();
+                let status = StatusCode::from_u16(code)
+                    .ok_or_else(|| ParseError::Malformed(format!("Invalid status {}", code)))?;
+
+                Ok(Some((
+                    parsed_len,
+                    ResponseHead {
+                        status,
+                        headers: collect_headers(resp.headers),
+                    },
+                )))
+            }
+            Ok(httparse::Status::Partial) => Ok(None),
+            Err(err) => Err(ParseError::Malformed(err.to_string())),
+        }
+    }
+}
+
+/// Convert a list of parsed httparse headers into an owned map
+fn collect_headers(headers: &[httparse::Header]) -> HeaderMap {
+    headers
+        .iter()
+        .map(|h| (h.name, String::from_utf8_lossy(h.value).to_string()))
+        .collect()
+}
+
+/// Read the head of a message from the stream
+///
+/// The parser borrows the buffer, so it is rerun from scratch every time the buffer grows.
+/// This is quadratic in theory, but the head size is capped by the limits anyway.
+fn read_head<H: Head, R: BufRead>(reader: &mut R, limits: &Limits) -> Result<H, ParseError> {
+    let mut buf = Vec::new();
+
+    loop {
+        let available = reader.fill_buf().map_err(ParseError::Io)?;
+        if available.is_empty() {
+            return Err(if buf.is_empty() {
+                ParseError::ConnectionClosed
+            } else {
+                ParseError::Incomplete
+            });
+        }
+        let previous_len = buf.len();
+        let read_len = available.len();
+        buf.extend_from_slice(available);
+
+        match H::parse(&buf)? {
+            Some((parsed_len, head)) => {
+                if parsed_len > limits.max_head_size {
+                    return Err(ParseError::TooLarge);
+                }
+                // Whatever follows the head is left in the reader for the body
+                reader.consume(parsed_len - previous_len);
+                return Ok(head);
+            }
+            None => {
+                reader.consume(read_len);
+                if buf.len() > limits.max_head_size {
+                    return Err(ParseError::TooLarge);
+                }
+            }
+        }
+    }
+}
+
+/// Extract the value of the Content-Length header, if any
+fn content_length(headers: &HeaderMap) -> Result<Option<usize>, ParseError> {
+    headers
+        .content_length()
+        .map_err(|err| ParseError::Malformed(err.to_string()))
+}
+
+/// Check whether the body is sent with the chunked transfer-encoding
+///
+/// Chunked is the only coding supported, and only on its own: the body of `gzip, chunked`
+/// would still be compressed once the chunks are read, so such messages are refused. Coding
+/// names are case-insensitive, and may be spread over several Transfer-Encoding headers.
+fn is_chunked(headers: &HeaderMap) -> Result<bool, ParseError> {
+    let codings: Vec<&str> = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .collect();
+    match codings.as_slice() {
+        [] => Ok(false),
+        [coding] if coding.eq_ignore_ascii_case("chunked") => Ok(true),
+        codings => Err(ParseError::Malformed(format!(
+            "Unsupported Transfer-Encoding: {}",
+            codings.join(", ")
+        ))),
+    }
+}
+
+/// Read exactly `length` bytes of body from the stream
+fn read_body<R: BufRead>(
+    reader: &mut R,
+    length: usize,
+    limits: &Limits,
+) -> Result<Vec<u8>, ParseError> {
+    if length > limits.max_body_size {
+        return Err(ParseError::TooLarge);
+    }
+
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => ParseError::Incomplete,
+            _ => ParseError::Io(err),
+        })?;
+    Ok(body)
+}
+
+/// Read the body until the other end closes the connection
+fn read_body_to_end<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    // Read one byte past the limit to tell apart a body of exactly the maximum size
+    let cap = (limits.max_body_size as u64).saturating_add(1);
+    reader
+        .take(cap)
+        .read_to_end(&mut body)
+        .map_err(ParseError::Io)?;
+
+    if body.len() > limits.max_body_size {
+        Err(ParseError::TooLarge)
+    } else {
+        Ok(body)
+    }
+}
+
+/// Parse an HTTP request from a byte stream
+///
+/// The body is read according to Transfer-Encoding or Content-Length, a request with neither
+/// has no body. Trailers of chunked requests are appended to the headers.
+///
+/// Requests with both Transfer-Encoding and Content-Length are refused (RFC 9112 §6.1), since
+/// another server on the way may have delimited the body differently.
+pub(crate) fn parse_request<R: BufRead>(
+    reader: &mut R,
+    limits: &Limits,
+) -> Result<Request, ParseError> {
+    let mut head: RequestHead = read_head(reader, limits)?;
+
+    if head.headers.contains("Transfer-Encoding") && head.headers.contains("Content-Length") {
+        return Err(ParseError::Malformed(
+            "Both Transfer-Encoding and Content-Length are present".to_string(),
+        ));
+    }
+    let body = if is_chunked(&head.headers)? {
+        let (body, trailers) = read_chunked_body(reader, limits)?;
+        head.headers.extend(trailers);
+        body
+    } else {
+        let length = content_length(&head.headers)?.unwrap_or(0);
+        read_body(reader, length, limits)?
+    };
+
+    let mut request = Request::new(head.method, &head.path, head.headers, body);
+    request.version = head.version;
+    Ok(request)
+}
+
+/// Parse an HTTP response from a byte stream
+///
+/// The body is read according to Transfer-Encoding or Content-Length, or until the connection
+/// is closed if both are missing. Informational, 204 and 304 responses never have a body.
+/// Trailers of chunked responses are appended to the headers.
+///
+/// The response is expected to answer a request with a body, use `parse_response_to` for
+/// responses to HEAD requests.
+pub fn parse_response<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Response, ParseError> {
+    parse_response_to(reader, Method::Get, limits)
+}
+
+/// Parse the HTTP response to a request made with the given method
+///
+/// Responses to HEAD requests describe the body they would have had in their headers, but never
+/// carry one.
+pub fn parse_response_to<R: BufRead>(
+    reader: &mut R,
+    method: Method,
+    limits: &Limits,
+) -> Result<Response, ParseError> {
+    let mut head: ResponseHead = read_head(reader, limits)?;
+    let has_body = !(method == Method::Head
+        || head.status.is_informational()
+        || head.status == StatusCode::NO_CONTENT
+        || head.status == StatusCode::NOT_MODIFIED);
+
+    let body = if !has_body {
+        Vec::new()
+    } else if is_chunked(&head.headers)? {
+        let (body, trailers) = read_chunked_body(reader, limits)?;
+        head.headers.extend(trailers);
+        body
+    } else {
+        match content_length(&head.headers)? {
+            Some(length) => read_body(reader, length, limits)?,
+            None => read_body_to_end(reader, limits)?,
+        }
+    };
+
+    Ok(Response {
+        status: head.status,
+        headers: head.headers,
+        body,
+        stream: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_parse_simple_request() {
+        let req_str = concat!(
+            "GET / HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.68.0\r\n",
+            "Accept: */*\r\n\r\n"
+        );
+        let mut buf_reader = BufReader::new(req_str.as_bytes());
+
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+
+        assert_eq!(parsed_req.method, Method::Get);
+        assert_eq!(parsed_req.path, "/");
+        assert_eq!(parsed_req.headers.len(), 3);
+        assert_eq!(parsed_req.text().unwrap(), "");
+    }
+
+    #[test]
+    fn test_parse_request_version() {
+        let req_str = b"GET / HTTP/1.0\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let mut buf_reader = BufReader::new(&req_str[..]);
+
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_req.version, Version::Http10);
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_req.version, Version::Http11);
+    }
+
+    #[test]
+    fn test_parse_incomplete_request() {
+        let req_str =
+            b"GET / HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.68.0\r\nAccept: */*";
+        let mut buf_reader = BufReader::new(&req_str[..]);
+
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default());
+
+        assert!(matches!(parsed_req, Err(ParseError::Incomplete)));
+    }
+
+    #[test]
+    fn test_parse_request_with_body() {
+        let body = "{ \"content\": \"Hello, world!\" }";
+        let req_str = format!(
+            concat!(
+                "POST / HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.68.0\r\n",
+                "Accept: */*\r\nContent-Length: {}\r\n\r\n{}"
+            ),
+            body.len(),
+            body
+        );
+
+        let mut buf_reader = BufReader::new(req_str.as_bytes());
+
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+
+        assert_eq!(parsed_req.method, Method::Post);
+        assert_eq!(parsed_req.path, "/");
+        assert_eq!(parsed_req.headers.len(), 4);
+        assert_eq!(parsed_req.text().unwrap(), body);
+    }
+
+    #[test]
+    fn test_parse_large_request() {
+        let body = "a".repeat(100_000);
+        let req_str = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        // Tiny buffer to force the parser to go through many reads
+        let mut buf_reader = BufReader::with_capacity(7, req_str.as_bytes());
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+
+        assert_eq!(parsed_req.method, Method::Post);
+        assert_eq!(parsed_req.text().unwrap(), body);
+    }
+
+    #[test]
+    fn test_parse_request_over_limits() {
+        let limits = Limits {
+            max_head_size: 64,
+            max_body_size: 16,
+        };
+
+        let req_str = b"POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\naaaaaaaaaaaaaaaaa";
+        let parsed_req = parse_request(&mut BufReader::new(&req_str[..]), &limits);
+        assert!(matches!(parsed_req, Err(ParseError::TooLarge)));
+
+        let req_str = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n", "a".repeat(64));
+        let parsed_req = parse_request(&mut BufReader::new(req_str.as_bytes()), &limits);
+        assert!(matches!(parsed_req, Err(ParseError::TooLarge)));
+
+        let req_str = b"POST / HTTP/1.1\r\nContent-Length: 16\r\n\r\naaaaaaaaaaaaaaaa";
+        let parsed_req = parse_request(&mut BufReader::new(&req_str[..]), &limits);
+        assert!(parsed_req.is_ok());
+    }
+
+    #[test]
+    fn test_parse_request_leaves_trailing_data() {
+        let req_str = b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nokGET /next HTTP/1.1\r\n\r\n";
+        let mut buf_reader = BufReader::new(&req_str[..]);
+
+        let first = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(first.text().unwrap(), "ok");
+
+        let second = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(second.path, "/next");
+
+        let third = parse_request(&mut buf_reader, &Limits::default());
+        assert!(matches!(third, Err(ParseError::ConnectionClosed)));
+    }
+
+    #[test]
+    fn test_parse_simple_response() {
+        let req_str = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let mut buf_reader = BufReader::new(&req_str[..]);
+
+        let parsed_req = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+
+        assert_eq!(parsed_req.status, StatusCode::OK);
+        assert_eq!(parsed_req.headers.len(), 1);
+        assert_eq!(parsed_req.text().unwrap(), "");
+    }
+
+    #[test]
+    fn test_parse_response_with_body() {
+        let body = "{ \"content\": \"Hello, world!\" }";
+        let req_str = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        let mut buf_reader = BufReader::new(req_str.as_bytes());
+        let parsed_req = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+
+        assert_eq!(parsed_req.status, StatusCode::OK);
+        assert_eq!(parsed_req.headers.len(), 1);
+        assert_eq!(parsed_req.text().unwrap(), body);
+    }
+
+    #[test]
+    fn test_parse_large_response() {
+        let body = "a".repeat(5 * 1024 * 1024);
+        let resp_str = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        let mut buf_reader = BufReader::with_capacity(13, resp_str.as_bytes());
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::unbounded_body()).unwrap();
+        assert_eq!(parsed_resp.text().unwrap(), body);
+
+        let mut buf_reader = BufReader::new(resp_str.as_bytes());
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::default());
+        assert!(matches!(parsed_resp, Err(ParseError::TooLarge)));
+    }
+
+    #[test]
+    fn test_parse_response_until_close() {
+        let resp_str = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nHello, world!";
+        let mut buf_reader = BufReader::new(&resp_str[..]);
+
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_resp.text().unwrap(), "Hello, world!");
+
+        let limits = Limits {
+            max_head_size: 1024,
+            max_body_size: 12,
+        };
+        let mut buf_reader = BufReader::new(&resp_str[..]);
+        let parsed_resp = parse_response(&mut buf_reader, &limits);
+        assert!(matches!(parsed_resp, Err(ParseError::TooLarge)));
+    }
+
+    #[test]
+    fn test_parse_response_without_body() {
+        let resp_str = b"HTTP/1.1 204 No Content\r\n\r\nHTTP/1.1 200 OK\r\n\r\n";
+        let mut buf_reader = BufReader::new(&resp_str[..]);
+
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_resp.status, StatusCode::NO_CONTENT);
+        assert_eq!(parsed_resp.text().unwrap(), "");
+
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_resp.status, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_parse_response_to_head() {
+        let resp_str =
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n";
+        let mut buf_reader = BufReader::new(&resp_str[..]);
+
+        let parsed_resp =
+            parse_response_to(&mut buf_reader, Method::Head, &Limits::default()).unwrap();
+        assert_eq!(parsed_resp.status, StatusCode::OK);
+        assert_eq!(parsed_resp.headers.content_length(), Ok(Some(5)));
+        assert!(parsed_resp.body.is_empty());
+
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_resp.status, StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn test_parse_chunked_request() {
+        let req_str = concat!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTrailer: X-Checksum\r\n\r\n",
+            "5\r\nHello\r\n8\r\n, world!\r\n0\r\nX-Checksum: 42\r\n\r\n",
+            "GET /next HTTP/1.1\r\n\r\n"
+        );
+        let mut buf_reader = BufReader::new(req_str.as_bytes());
+
+        let parsed_req = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_req.text().unwrap(), "Hello, world!");
+        assert_eq!(parsed_req.headers.get("x-checksum"), Some("42"));
+
+        let next = parse_request(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(next.path, "/next");
+    }
+
+    #[test]
+    fn test_parse_chunked_response() {
+        let resp_str = concat!(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+            "5\r\nHello\r\n0\r\n\r\n"
+        );
+        let mut buf_reader = BufReader::new(resp_str.as_bytes());
+
+        // Transfer-Encoding takes precedence over Content-Length
+        let parsed_resp = parse_response(&mut buf_reader, &Limits::default()).unwrap();
+        assert_eq!(parsed_resp.text().unwrap(), "Hello");
+    }
+
+    #[test]
+    fn test_parse_unsupported_transfer_encoding() {
+        let req_str = b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n";
+        let parsed_req = parse_request(&mut BufReader::new(&req_str[..]), &Limits::default());
+        assert!(matches!(parsed_req, Err(ParseError::Malformed(_))));
+
+        // Chunked isn't enough when other codings were applied before
+        let req_str = b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n";
+        let parsed_req = parse_request(&mut BufReader::new(&req_str[..]), &Limits::default());
+        assert!(matches!(parsed_req, Err(ParseError::Malformed(_))));
+
+        let req_str = concat!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "0\r\n\r\n"
+        );
+        let parsed_req = parse_request(&mut BufReader::new(req_str.as_bytes()), &Limits::default());
+        assert!(matches!(parsed_req, Err(ParseError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_parse_chunked_is_case_insensitive() {
+        let req_str =
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n5\r\nHello\r\n0\r\n\r\n";
+        let parsed_req =
+            parse_request(&mut BufReader::new(&req_str[..]), &Limits::default()).unwrap();
+        assert_eq!(parsed_req.text().unwrap(), "Hello");
+    }
+
+    #[test]
+    fn test_parse_request_with_both_lengths() {
+        let req_str = concat!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+            "5\r\nHello\r\n0\r\n\r\n"
+        );
+        let parsed_req = parse_request(&mut BufReader::new(req_str.as_bytes()), &Limits::default());
+        assert!(matches!(parsed_req, Err(ParseError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_parse_unknown_method() {
+        let req_str = b"DELTE / HTTP/1.1\r\n\r\n";
+        let parsed_req = parse_request(&mut BufReader::new(&req_str[..]), &Limits::default());
+        assert!(matches!(parsed_req, Err(ParseError::UnknownMethod(m)) if m == "DELTE"));
+    }
+
+    #[test]
+    fn test_parse_lowercase_content_length() {
+        let req_str = b"POST / HTTP/1.1\r\ncontent-length: 5\r\n\r\nHello";
+        let parsed_req =
+            parse_request(&mut BufReader::new(&req_str[..]), &Limits::default()).unwrap();
+        assert_eq!(parsed_req.text().unwrap(), "Hello");
+    }
+
+    #[test]
+    fn test_parse_binary_body() {
+        let mut req = b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\n".to_vec();
+        req.extend_from_slice(&[0xff, 0x00, 0xfe, 0x80]);
+
+        let parsed_req = parse_request(&mut BufReader::new(&req[..]), &Limits::default()).unwrap();
+        assert_eq!(parsed_req.body, vec![0xff, 0x00, 0xfe, 0x80]);
+        assert!(parsed_req.text().is_err());
+    }
+
+    #[test]
+    fn test_parse_request_with_query() {
+        let req_str = b"GET /api/v1/orders/1?verbose=true HTTP/1.1\r\n\r\n";
+        let parsed_req =
+            parse_request(&mut BufReader::new(&req_str[..]), &Limits::default()).unwrap();
+
+        assert_eq!(parsed_req.path, "/api/v1/orders/1");
+        assert_eq!(parsed_req.query.get("verbose"), Some("true"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Splitting of request targets into path and query string
+use crate::errors;
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+
+/// Parameters of the query string of a request
+///
+/// Names and values are percent-decoded. A name may appear several times, all the values are kept
+/// in the order they were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    raw: String,
+    params: Vec<(String, String)>,
+}
+
+impl Query {
+    /// Parse a query string, without the leading '?'
+    ///
+    /// Parsing never fails. Each pair is decoded on its own: malformed percent sequences are
+    /// kept as is and invalid UTF-8 is replaced, without affecting the other pairs. A pair
+    /// without '=' is a name with an empty value.
+    pub fn parse(raw: &str) -> Query {
+        let params = raw
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(name), decode_component(value))
+            })
+            .collect();
+        Query {
+            raw: raw.to_string(),
+            params,
+        }
+    }
+
+    /// First value associated with the name, if any
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// All the values associated with the name, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.params
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Number of parameters
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Check whether the query string was empty
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Iterate over the (name, value) pairs
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The query string as it was received, without the leading '?'
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Deserialize the parameters into a typed structure
+    ///
+    /// Fails with a BadRequest if the query doesn't fit T, so handlers can forward the error as
+    /// is. The message comes from serde_urlencoded and doesn't always name the parameter.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> errors::Result<T> {
+        serde_urlencoded::from_str(&self.raw)
+            .map_err(|err| errors::Error::BadRequest(format!("Invalid query: {}", err)).into())
+    }
+}
+
+/// Percent-decode a name or value of a query string, where '+' stands for a space
+fn decode_component(component: &str) -> String {
+    let component = component.replace('+', " ");
+    percent_decode_str(&component)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Percent-decode a path parameter once the path is routed
+///
+/// Invalid UTF-8 sequences are replaced, malformed percent sequences kept as is.
+pub(crate) fn decode_path_segment(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+/// Split a request target into its path and its query
+///
+/// The path is kept encoded, so that an encoded '/' doesn't split a segment when routing. The
+/// path parameters are decoded once the path is matched.
+pub(crate) fn split_target(target: &str) -> (String, Query) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    (path.to_string(), Query::parse(query))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_target() {
+        let (path, query) = split_target("/api/v1/orders/1?verbose=true&tag=a&tag=b%20c");
+        assert_eq!(path, "/api/v1/orders/1");
+        assert_eq!(query.get("verbose"), Some("true"));
+        assert_eq!(query.get_all("tag").collect::<Vec<_>>(), vec!["a", "b c"]);
+        assert_eq!(query.len(), 3);
+
+        let (path, query) = split_target("/menu/Caf%C3%A9%20au%20lait");
+        assert_eq!(path, "/menu/Caf%C3%A9%20au%20lait");
+        assert!(query.is_empty());
+        assert_eq!(decode_path_segment("Caf%C3%A9%20au%20lait"), "Café au lait");
+        assert_eq!(decode_path_segment("A%2FB%zz"), "A/B%zz");
+
+        let (path, query) = split_target("/?");
+        assert_eq!(path, "/");
+        assert!(query.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_query() {
+        let query = Query::parse("a=1&b=%zz&c=%FF&flag&d=x+y&&e=2=3");
+        assert_eq!(query.get("a"), Some("1"));
+        // A bad pair doesn't drop the others
+        assert_eq!(query.get("b"), Some("%zz"));
+        assert_eq!(query.get("c"), Some("\u{FFFD}"));
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get("d"), Some("x y"));
+        assert_eq!(query.get("e"), Some("2=3"));
+        assert_eq!(query.len(), 6);
+    }
+
+    #[test]
+    fn test_typed_query() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Pagination {
+            limit: u32,
+            offset: Option<u32>,
+        }
+
+        let query = Query::parse("limit=10&offset=20");
+        assert_eq!(
+            query.deserialize::<Pagination>().unwrap(),
+            Pagination {
+                limit: 10,
+                offset: Some(20)
+            }
+        );
+
+        let query = Query::parse("limit=10");
+        assert_eq!(query.deserialize::<Pagination>().unwrap().offset, None);
+
+        let query = Query::parse("limit=ten");
+        assert!(query.deserialize::<Pagination>().is_err());
+    }
+}
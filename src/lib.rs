@@ -1,4 +1,5 @@
 pub mod api;
+pub mod billing;
 pub mod routes;
 pub mod errors;
 pub mod http;
@@ -6,3 +7,5 @@ pub mod threadpool;
 pub mod database;
 pub mod endpoints;
 pub mod cli;
+pub mod clock;
+pub mod random;
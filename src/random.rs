@@ -0,0 +1,60 @@
+//! Source of random numbers
+//!
+//! Random numbers are drawn through the `RandomSource` trait rather than directly from the
+//! thread's generator, so that the values depending on them can be reproduced in tests.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// Gives random numbers
+pub trait RandomSource: Send + Sync {
+    /// Random number in the given range, which must not be empty
+    fn gen_range(&self, range: Range<u32>) -> u32;
+}
+
+/// Random numbers from the generator of the current thread
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadRandom;
+
+impl RandomSource for ThreadRandom {
+    fn gen_range(&self, range: Range<u32>) -> u32 {
+        rand::thread_rng().gen_range(range)
+    }
+}
+
+/// Reproducible random numbers, the same seed always giving the same sequence
+#[derive(Debug)]
+pub struct SeededRandom(Mutex<StdRng>);
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom(Mutex::new(StdRng::seed_from_u64(seed)))
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn gen_range(&self, range: Range<u32>) -> u32 {
+        // A panic on an empty range leaves the generator in a perfectly usable state
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .gen_range(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_random() {
+        let first = SeededRandom::new(42);
+        let second = SeededRandom::new(42);
+        for _ in 0..10 {
+            let value = first.gen_range(5..15);
+            assert!((5..15).contains(&value));
+            assert_eq!(value, second.gen_range(5..15));
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Extractors parsing the parts of a request a handler works with
+//!
+//! Handlers taking extractors instead of the raw request are wrapped with `extract` before
+//! being added to the router. The extraction runs before the handler, and its failures are
+//! reported as InvalidField errors naming the faulty parameter or field.
+use super::HttpParams;
+use crate::errors::{Error, Result};
+use crate::http::{Request, Response};
+use serde::de::DeserializeOwned;
+use std::str::FromStr;
+
+/// Value that can be built from an incoming request
+pub trait FromRequest: Sized {
+    /// Extract the value from the request and the parameters of the matching route
+    fn from_request(request: &Request, params: &HttpParams) -> Result<Self>;
+}
+
+/// Value that can be built from the parameters of a path
+pub trait FromParams: Sized {
+    /// Parse the parameters, in the order they appear in the path
+    fn from_params(params: &HttpParams) -> Result<Self>;
+}
+
+/// Parameters of the path, parsed into `T`
+///
+/// `T` is either a single value for routes with one parameter, or a tuple with one element per
+/// parameter, in the order they appear in the route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+/// JSON body of the request, deserialized into `T`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+/// Nothing to extract, for handlers that only need the state
+impl FromRequest for () {
+    fn from_request(_: &Request, _: &HttpParams) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl<T: FromParams> FromRequest for Path<T> {
+    fn from_request(_: &Request, params: &HttpParams) -> Result<Self> {
+        T::from_params(params).map(Path)
+    }
+}
+
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    fn from_request(request: &Request, _: &HttpParams) -> Result<Self> {
+        request.json().map(Json)
+    }
+}
+
+/// Parse the value of a single path parameter
+fn parse_param<T>(name: &str, value: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|err: T::Err| {
+        Error::InvalidField {
+            field: name.to_string(),
+            message: err.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Check that the route has as many parameters as the handler expects
+///
+/// A mismatch means the handler was attached to the wrong route, which is a programming error
+/// rather than a problem with the request.
+fn expect_params(params: &HttpParams, count: usize) -> Result<()> {
+    if params.len() == count {
+        Ok(())
+    } else {
+        Err(Error::InternalServerError(format!(
+            "Expected {} path parameters, the route has {}",
+            count,
+            params.len()
+        ))
+        .into())
+    }
+}
+
+/// Implement FromParams for routes with a single parameter of the given types
+macro_rules! impl_from_single_param {
+    ($($type:ty),*) => {
+        $(
+            impl FromParams for $type {
+                fn from_params(params: &HttpParams) -> Result<Self> {
+                    expect_params(params, 1)?;
+                    let (name, value) = params.iter().next().unwrap();
+                    parse_param(name, value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_single_param!(u32, u64, i32, i64, usize, String);
+
+/// Implement FromParams for tuples of parameters, each element parsed from its string
+macro_rules! impl_from_params_tuple {
+    ($count:literal: $($type:ident),+) => {
+        impl<$($type),+> FromParams for ($($type,)+)
+        where
+            $($type: FromStr, $type::Err: std::fmt::Display,)+
+        {
+            fn from_params(params: &HttpParams) -> Result<Self> {
+                expect_params(params, $count)?;
+                let mut params = params.iter();
+                Ok(($({
+                    let (name, value) = params.next().unwrap();
+                    parse_param::<$type>(name, value)?
+                },)+))
+            }
+        }
+    };
+}
+
+impl_from_params_tuple!(1: A);
+impl_from_params_tuple!(2: A, B);
+impl_from_params_tuple!(3: A, B, C);
+
+/// Implement FromRequest for tuples of extractors, run from left to right
+macro_rules! impl_from_request_tuple {
+    ($($type:ident),+) => {
+        impl<$($type: FromRequest),+> FromRequest for ($($type,)+) {
+            fn from_request(request: &Request, params: &HttpParams) -> Result<Self> {
+                Ok(($($type::from_request(request, params)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_request_tuple!(A, B);
+impl_from_request_tuple!(A, B, C);
+
+/// Turn a handler taking extractors into one the router accepts
+///
+/// The handler receives the extracted value and the state of the application. Several
+/// extractors can be combined in a tuple, e.g. `(Path<u32>, Json<NewOrder>)`.
+pub fn extract<S, E, F>(
+    handler: F,
+) -> impl Fn(Request, HttpParams, &S) -> Result<Response> + Send + Sync + 'static
+where
+    S: ?Sized,
+    E: FromRequest,
+    F: Fn(E, &S) -> Result<Response> + Send + Sync + 'static,
+{
+    move |request: Request, params: HttpParams, state: &S| {
+        handler(E::from_request(&request, &params)?, state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::routes::make_params;
+    use crate::routes::params;
+    use std::cell::Cell;
+
+    /// Check that the result is an InvalidField error for the given field
+    fn assert_invalid_field<T: std::fmt::Debug>(result: Result<T>, expected: &str) {
+        let err = result.unwrap_err();
+        match err.downcast_ref::<Error>() {
+            Some(Error::InvalidField { field, .. }) => assert_eq!(field, expected),
+            _ => panic!("Expected an invalid '{}', got {:?}", expected, err),
+        }
+    }
+
+    #[test]
+    fn test_path() {
+        let request = Request::get("/");
+
+        let params = make_params!(ORDER_ID: 1, ITEM_ID: 2);
+        let Path((order_id, item_id)) =
+            Path::<(u32, u32)>::from_request(&request, &params).unwrap();
+        assert_eq!((order_id, item_id), (1, 2));
+
+        let params = make_params!(ORDER_ID: 42);
+        let Path(order_id) = Path::<u32>::from_request(&request, &params).unwrap();
+        assert_eq!(order_id, 42);
+
+        let params = make_params!(ORDER_ID: 1, ITEM_ID: "two");
+        assert_invalid_field(
+            Path::<(u32, u32)>::from_request(&request, &params),
+            params::ITEM_ID,
+        );
+
+        let params = make_params!(ORDER_ID: 1);
+        let err = Path::<(u32, u32)>::from_request(&request, &params).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InternalServerError(_))
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Body {
+            #[allow(dead_code)]
+            items: Vec<u32>,
+        }
+
+        let params = make_params!();
+
+        let request = Request::post("/", "{\"items\": [1, 2]}");
+        assert!(Json::<Body>::from_request(&request, &params).is_ok());
+
+        let request = Request::post("/", "{\"items\": [1, \"two\"]}");
+        assert_invalid_field(Json::<Body>::from_request(&request, &params), "items[1]");
+
+        let request = Request::post("/", "not json");
+        let err = Json::<Body>::from_request(&request, &params).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_extract() {
+        let handler = extract(
+            |(Path(order_id), Json(items)): (Path<u32>, Json<Vec<String>>), calls: &Cell<u32>| {
+                calls.set(calls.get() + 1);
+                Ok(Response::ok_with_body(format!(
+                    "{}: {}",
+                    order_id,
+                    items.join(", ")
+                )))
+            },
+        );
+        let calls = Cell::new(0);
+
+        let response = handler(
+            Request::post("/", "[\"Pizza\", \"Soda\"]"),
+            make_params!(ORDER_ID: 3),
+            &calls,
+        )
+        .unwrap();
+        assert_eq!(response.text().unwrap(), "3: Pizza, Soda");
+
+        // The handler doesn't run if the extraction fails
+        let result = handler(
+            Request::post("/", "[]"),
+            make_params!(ORDER_ID: "x"),
+            &calls,
+        );
+        assert_invalid_field(result, params::ORDER_ID);
+        assert_eq!(calls.get(), 1);
+    }
+}
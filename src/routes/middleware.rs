@@ -0,0 +1,330 @@
+//! Middlewares wrapping the request handlers of the router
+//!
+//! A middleware sees every request before it is routed and every response (or error) produced
+//! for it. It can modify both, or answer on its own without calling the rest of the chain.
+use super::HttpRouter;
+use crate::api::ErrorResponse;
+use crate::errors::{BoxedError, Error, Result};
+use crate::http::{Request, Response, StatusCode};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Instant;
+
+/// Processing step applied around the handlers of a router
+///
+/// Implementations call `next.run` to pass the request down the chain, and get back the result
+/// of the handler as transformed by the middlewares added after them.
+pub trait Middleware<S: ?Sized>: Send + Sync {
+    /// Process the request, usually by forwarding it to `next`
+    fn handle(&self, request: Request, state: &S, next: Next<'_, S>) -> Result<Response>;
+}
+
+/// Rest of the middleware chain, ending with the router itself
+pub struct Next<'a, S: ?Sized> {
+    middlewares: &'a [Box<dyn Middleware<S>>],
+    router: &'a HttpRouter<S>,
+}
+
+impl<'a, S: ?Sized> Next<'a, S> {
+    /// Create the chain running the given middlewares, then the handlers of the router
+    pub(super) fn new(
+        middlewares: &'a [Box<dyn Middleware<S>>],
+        router: &'a HttpRouter<S>,
+    ) -> Self {
+        Next {
+            middlewares,
+            router,
+        }
+    }
+
+    /// Pass the request to the next middleware, or to the handler if this was the last one
+    pub fn run(self, request: Request, state: &S) -> Result<Response> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => {
+                middleware.handle(request, state, Next::new(rest, self.router))
+            }
+            None => self.router.dispatch(request, state),
+        }
+    }
+}
+
+/// Convert an error into the response sent to the client
+///
+/// Application errors are mapped to the matching status codes, anything else is unexpected and
+/// becomes a 500. Only invalid or conflicting requests get a body describing the problem (see
+/// ErrorResponse), other bodies are left empty to avoid leaking information about the server.
+pub fn error_response(err: BoxedError) -> Response {
+    eprintln!("Error processing request: {}", err);
+    match err.downcast_ref::<Error>() {
+        Some(Error::NotFound(_)) => Response::error(StatusCode::NOT_FOUND),
+        Some(Error::BadRequest(message)) => with_body(
+            StatusCode::BAD_REQUEST,
+            ErrorResponse {
+                error: message.clone(),
+                field: None,
+                items: Vec::new(),
+            },
+        ),
+        Some(Error::InvalidField { field, message }) => with_body(
+            StatusCode::BAD_REQUEST,
+            ErrorResponse {
+                error: message.clone(),
+                field: Some(field.clone()),
+                items: Vec::new(),
+            },
+        ),
+        Some(Error::Conflict(message)) => with_body(
+            StatusCode::CONFLICT,
+            ErrorResponse {
+                error: message.clone(),
+                field: None,
+                items: Vec::new(),
+            },
+        ),
+        Some(err @ Error::UnavailableItems(items)) => with_body(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorResponse {
+                error: err.to_string(),
+                field: Some("items".to_string()),
+                items: items.clone(),
+            },
+        ),
+        Some(Error::MethodNotAllowed(allowed)) => Response::method_not_allowed(allowed),
+        Some(Error::ServiceUnavailable(_)) => Response::service_unavailable(),
+        _ => Response::internal_server_error(),
+    }
+}
+
+/// Create an error response with the given description as body
+fn with_body(status: StatusCode, body: ErrorResponse) -> Response {
+    match Response::json(&body) {
+        Ok(mut response) => {
+            response.status = status;
+            response
+        }
+        Err(_) => Response::error(status),
+    }
+}
+
+/// Log every request with the status of its response and the time it took to produce it
+pub struct Logger;
+
+impl<S: ?Sized> Middleware<S> for Logger {
+    fn handle(&self, request: Request, state: &S, next: Next<'_, S>) -> Result<Response> {
+        let start = Instant::now();
+        let line = format!("{} {}", request.method, request.path);
+
+        let result = next.run(request, state);
+        match &result {
+            Ok(response) => println!("{} -> {} ({:?})", line, response.status, start.elapsed()),
+            Err(err) => println!("{} -> error: {} ({:?})", line, err, start.elapsed()),
+        }
+        result
+    }
+}
+
+/// Turn the errors of the handlers into HTTP responses, see `error_response`
+pub struct ErrorResponses;
+
+impl<S: ?Sized> Middleware<S> for ErrorResponses {
+    fn handle(&self, request: Request, state: &S, next: Next<'_, S>) -> Result<Response> {
+        Ok(next.run(request, state).unwrap_or_else(error_response))
+    }
+}
+
+/// Turn panics in the rest of the chain into InternalServerError
+///
+/// Without it a panicking handler takes down the worker thread, and the client is left without
+/// an answer. The state may have been left half-modified by the handler, so this is a last
+/// resort rather than a way of handling errors.
+pub struct CatchPanic;
+
+impl<S: ?Sized> Middleware<S> for CatchPanic {
+    fn handle(&self, request: Request, state: &S, next: Next<'_, S>) -> Result<Response> {
+        catch_unwind(AssertUnwindSafe(|| next.run(request, state))).unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|msg| msg.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(Error::InternalServerError(format!("Handler panicked: {}", message)).into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::Method;
+    use crate::routes::{endpoints, paths};
+    use std::cell::RefCell;
+
+    /// State of the test routers, recording the steps of the processing
+    type Steps = RefCell<Vec<String>>;
+
+    /// Router answering GET on the orders with the given handler, wrapped by the middleware
+    fn router_with<M, F>(middleware: M, handler: F) -> HttpRouter<Steps>
+    where
+        M: Middleware<Steps> + 'static,
+        F: Fn(Request, crate::routes::HttpParams, &Steps) -> Result<Response>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let mut router = HttpRouter::new().unwrap();
+        router.add_route(Method::Get, endpoints::ORDERS, handler);
+        router.add_middleware(middleware);
+        router
+    }
+
+    /// Middleware recording when it sees the request and the response
+    struct Trace(&'static str);
+
+    impl Middleware<Steps> for Trace {
+        fn handle(
+            &self,
+            request: Request,
+            state: &Steps,
+            next: Next<'_, Steps>,
+        ) -> Result<Response> {
+            state.borrow_mut().push(format!("{} before", self.0));
+            let result = next.run(request, state);
+            state.borrow_mut().push(format!("{} after", self.0));
+            result
+        }
+    }
+
+    #[test]
+    fn test_middleware_order() {
+        let mut router = router_with(Trace("outer"), |_, _, state: &Steps| {
+            state.borrow_mut().push("handler".to_string());
+            Ok(Response::ok())
+        });
+        router.add_middleware(Trace("inner"));
+
+        let state = Steps::default();
+        router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(
+            state.into_inner(),
+            vec![
+                "outer before",
+                "inner before",
+                "handler",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let router = router_with(ErrorResponses, |_, _, _| {
+            Err(Error::BadRequest("Invalid body".to_string()).into())
+        });
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json_body::<ErrorResponse>().unwrap(),
+            ErrorResponse {
+                error: "Invalid body".to_string(),
+                field: None,
+                items: Vec::new(),
+            }
+        );
+
+        let response = router
+            .route(Request::get("/api/v1/missing"), &state)
+            .unwrap();
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+        let response = router
+            .route(Request::post(paths::ORDERS, ""), &state)
+            .unwrap();
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+    }
+
+    #[test]
+    fn test_invalid_field_response() {
+        let router = router_with(ErrorResponses, |_, _, _| {
+            Err(Error::InvalidField {
+                field: "order_id".to_string(),
+                message: "invalid digit found in string".to_string(),
+            }
+            .into())
+        });
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers.content_type(), Some("application/json"));
+        assert_eq!(
+            response.json_body::<ErrorResponse>().unwrap().field,
+            Some("order_id".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unavailable_items_response() {
+        let router = router_with(ErrorResponses, |_, _, _| {
+            Err(Error::UnavailableItems(vec!["Piza".to_string()]).into())
+        });
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.json_body::<ErrorResponse>().unwrap();
+        assert_eq!(body.field.as_deref(), Some("items"));
+        assert_eq!(body.items, vec!["Piza"]);
+    }
+
+    #[test]
+    fn test_conflict_response() {
+        let router = router_with(ErrorResponses, |_, _, _| {
+            Err(Error::Conflict("Item 1 is already served".to_string()).into())
+        });
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        assert_eq!(
+            response.json_body::<ErrorResponse>().unwrap().error,
+            "Item 1 is already served"
+        );
+    }
+
+    #[test]
+    fn test_service_unavailable_response() {
+        let router = router_with(ErrorResponses, |_, _, _| {
+            Err(Error::ServiceUnavailable("No connection available".to_string()).into())
+        });
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers.get("Retry-After"), Some("1"));
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let mut router = router_with(ErrorResponses, |_, _, _| panic!("Oops"));
+        router.add_middleware(CatchPanic);
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_logger_forwards_result() {
+        let router = router_with(Logger, |_, _, _| Ok(Response::ok_with_body("Hello")));
+        let state = Steps::default();
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.text().unwrap(), "Hello");
+        assert!(router
+            .route(Request::get("/api/v1/missing"), &state)
+            .is_err());
+    }
+}
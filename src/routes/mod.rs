@@ -0,0 +1,598 @@
+use std::collections::HashMap;
+
+use crate::{
+    errors,
+    http::{allow_header, decode_path_segment, Method, Request, Response},
+};
+use errors::{Error, Result};
+use matchit::Router;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+pub mod extract;
+pub mod middleware;
+
+pub use extract::{extract, FromRequest, Json, Path};
+pub use middleware::{Middleware, Next};
+
+/// Utility macro generating a constant for the HTTP endpoint, and associate it with
+/// an identifier. Matchit requires both
+macro_rules! make_paths {
+        ($($name:ident: $path:expr,)*) => {
+
+        pub mod paths {
+                    $(
+                        pub const $name: &str = concat!("/api/v1", $path);
+                    )*
+        }
+        pub mod endpoints {
+            $(
+                pub const $name: &str = stringify!($name);
+            )*
+        }
+
+        }
+    }
+
+make_paths! {
+    ORDERS: "/orders",
+    ORDER_BY_ID: "/orders/{order_id}",
+    ITEMS: "/orders/{order_id}/items", // not actually used, but someday maybe
+    ITEM_BY_ID: "/orders/{order_id}/items/{item_id}",
+    BILL: "/orders/{order_id}/bill",
+    SPLIT_BILL: "/orders/{order_id}/bill/split",
+    CHECKOUT: "/orders/{order_id}/checkout",
+    MENU: "/menu",
+    MENU_ENTRY: "/menu/{name}",
+}
+
+/// Utility to add a list of paths to the router automatically
+macro_rules! add_path{
+    ($router:ident $(, $path:ident)*) => {
+        $(
+            $router.insert(paths::$path, endpoints::$path)?;
+        )*
+    }
+}
+
+/// Names of the parameters in the HTTP paths, used to extract them
+/// from the parameters inside of request handling
+pub mod params {
+    /// Key of order ids in HTTP paths
+    pub const ORDER_ID: &str = "order_id";
+
+    /// Key of item ids in HTTP paths
+    pub const ITEM_ID: &str = "item_id";
+
+    /// Key of menu entry names in HTTP paths
+    pub const NAME: &str = "name";
+}
+
+/// Return the HTTP path for an order based on its id
+pub fn order_by_id(order_id: u32) -> String {
+    paths::ORDER_BY_ID.replace("{order_id}", &order_id.to_string())
+}
+
+/// Return the HTTP path for an item based on its order id and item id
+pub fn item_by_id(order_id: u32, item_id: u32) -> String {
+    paths::ITEM_BY_ID
+        .replace("{order_id}", &order_id.to_string())
+        .replace("{item_id}", &item_id.to_string())
+}
+
+/// Return the HTTP path for the bill of an order based on its id
+pub fn order_bill(order_id: u32) -> String {
+    paths::BILL.replace("{order_id}", &order_id.to_string())
+}
+
+/// Return the HTTP path to split the bill of an order based on its id
+pub fn order_split_bill(order_id: u32) -> String {
+    paths::SPLIT_BILL.replace("{order_id}", &order_id.to_string())
+}
+
+/// Return the HTTP path to check out an order based on its id
+pub fn order_checkout(order_id: u32) -> String {
+    paths::CHECKOUT.replace("{order_id}", &order_id.to_string())
+}
+
+/// Return the HTTP path for a menu entry based on its name
+///
+/// The name is percent-encoded, since it may contain spaces or slashes.
+pub fn menu_entry(name: &str) -> String {
+    paths::MENU_ENTRY.replace(
+        "{name}",
+        &utf8_percent_encode(name, NON_ALPHANUMERIC).to_string(),
+    )
+}
+
+// spurious warning, I am using this in tests
+#[allow(unused_macros)]
+/// Utility to create easily HttpParams for testing
+macro_rules! make_params {
+    () => {
+        $crate::routes::HttpParams::new()
+    };
+    ($name:ident: $value:expr $(, $name2:ident: $value2:expr)* ) => {
+        {
+            let mut map = $crate::routes::HttpParams::new();
+            map.insert(params::$name.to_string(), $value.to_string());
+            $(
+                map.insert(params::$name2.to_string(), $value2.to_string());
+            )*
+            map
+        }
+        }
+    }
+
+#[allow(unused_imports)]
+pub(crate) use make_params;
+
+/// Create a new router with the paths defined in this module
+///
+/// Errors from this functions are programming errors, most likely steming from a
+/// misuse of matchit
+/// TODO: refactor this to separate generic functions and data types and those specific to this
+/// application
+fn new_router() -> errors::Result<Router<&'static str>> {
+    let mut router = Router::new();
+    add_path!(
+        router,
+        ORDERS,
+        ORDER_BY_ID,
+        ITEMS,
+        ITEM_BY_ID,
+        BILL,
+        SPLIT_BILL,
+        CHECKOUT,
+        MENU,
+        MENU_ENTRY
+    );
+    Ok(router)
+}
+
+/// Parameters extracted from the HTTP path, passed to handlers
+///
+/// The parameters are kept in the order they appear in the path, so that they can be
+/// extracted positionally as well as by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpParams(Vec<(String, String)>);
+
+impl HttpParams {
+    /// Create an empty set of parameters
+    pub fn new() -> Self {
+        HttpParams(Vec::new())
+    }
+
+    /// Value of the parameter with the given name, if any
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set the value of a parameter, keeping its position if it already exists
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        let value = value.into();
+        match self.0.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, previous)) => *previous = value,
+            None => self.0.push((name, value)),
+        }
+    }
+
+    /// Number of parameters
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check whether there are no parameters
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over the (name, value) pairs in the order of the path
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HttpParams {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut params = HttpParams::new();
+        for (name, value) in iter {
+            params.insert(name, value);
+        }
+        params
+    }
+}
+
+/// Type of the function that handles HTTP requests
+///
+/// Handlers receive the state of the application along with the request, and may capture
+/// whatever else they need (configuration, clocks...) since they can be closures.
+pub type HttpHandler<S> = Box<dyn Fn(Request, HttpParams, &S) -> Result<Response> + Send + Sync>;
+
+/// Methods supported by a route, including the ones the router handles automatically
+///
+/// The methods are listed in the order of Method::ALL so that the Allow header is stable.
+fn allowed_methods<S: ?Sized>(method_to_handler: &HashMap<Method, HttpHandler<S>>) -> Vec<Method> {
+    Method::ALL
+        .into_iter()
+        .filter(|method| {
+            method_to_handler.contains_key(method)
+                || *method == Method::Options
+                || (*method == Method::Head && method_to_handler.contains_key(&Method::Get))
+        })
+        .collect()
+}
+
+/// The router is in charge of taking in raw HTTP requests and to dispatch them to
+/// the appropriate handler function.
+///
+/// It is generic over the state of the application `S`, which is handed to the handlers
+/// with each request. `S` may be unsized, typically a trait object like `dyn Database`.
+///
+/// Requests go through the middlewares, in the order they were added, before reaching the
+/// handler.
+pub struct HttpRouter<S: ?Sized> {
+    routes: Router<&'static str>,
+    handlers: HashMap<&'static str, HashMap<Method, HttpHandler<S>>>,
+    middlewares: Vec<Box<dyn Middleware<S>>>,
+}
+
+impl<S: ?Sized> HttpRouter<S> {
+    /// Creates a new empty router
+    ///
+    /// Although the matchit router is not empty, there are no methods associated
+    /// to the routes yet, so no request can be processed
+    /// Errors in this function are programming errors.
+    pub fn new() -> Result<Self> {
+        let routes = new_router()?;
+        Ok(HttpRouter {
+            routes,
+            handlers: HashMap::new(),
+            middlewares: Vec::new(),
+        })
+    }
+
+    /// Add a new route to the router
+    pub fn add_route<F>(&mut self, method: Method, route: &'static str, handler: F)
+    where
+        F: Fn(Request, HttpParams, &S) -> Result<Response> + Send + Sync + 'static,
+    {
+        let method_to_handler = self.handlers.entry(route).or_default();
+        method_to_handler.insert(method, Box::new(handler));
+    }
+
+    /// Add a middleware wrapping the handlers
+    ///
+    /// Middlewares are run in the order they are added: the first one sees the request first
+    /// and the response last.
+    pub fn add_middleware<M: Middleware<S> + 'static>(&mut self, middleware: M) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    /// Sends a request through the middlewares, then to the appropriate handler
+    ///
+    /// See `dispatch` for the routing itself.
+    pub fn route(&self, request: Request, state: &S) -> Result<Response> {
+        Next::new(&self.middlewares, self).run(request, state)
+    }
+
+    /// Sends a request to the appropriate handler if it exists
+    ///
+    /// If there is a route matching the request, its handler will be called and the result of the
+    /// function will be the result of the handler. If no route is defined for this request,
+    /// return Error::NotFound, and if the route exists but has no handler for the method, return
+    /// Error::MethodNotAllowed with the methods it supports.
+    ///
+    /// HEAD and OPTIONS are handled automatically for routes that don't define them: HEAD runs
+    /// the GET handler (the server drops the body when sending the response), OPTIONS answers
+    /// with the supported methods in the Allow header.
+    ///
+    /// Only the path takes part in the matching. The path parameters are handed to the handler
+    /// as HttpParams, the query string parameters are available in `request.query`.
+    ///
+    /// Checking that all parameters are presents and that the body is correct is the
+    /// responsibility of the handler
+    fn dispatch(&self, request: Request, state: &S) -> Result<Response> {
+        let route = self
+            .routes
+            .at(&request.path)
+            .map_err(|err| errors::Error::NotFound(err.to_string()))?;
+        let method_to_handler = self.handlers.get(route.value).ok_or_else(|| {
+            Error::NotFound(format!(
+                "No method associated to this route: {}",
+                route.value
+            ))
+        })?;
+        let handler = match (method_to_handler.get(&request.method), request.method) {
+            (Some(handler), _) => handler,
+            (None, Method::Head) if method_to_handler.contains_key(&Method::Get) => {
+                &method_to_handler[&Method::Get]
+            }
+            (None, Method::Options) => {
+                let mut response = Response::ok();
+                response
+                    .headers
+                    .insert("Allow", allow_header(&allowed_methods(method_to_handler)));
+                return Ok(response);
+            }
+            (None, _) => {
+                return Err(Error::MethodNotAllowed(allowed_methods(method_to_handler)).into())
+            }
+        };
+
+        // Decoded only now, so that an encoded '/' stays inside its segment
+        let params: HttpParams = route
+            .params
+            .iter()
+            .map(|(name, value)| (name, decode_path_segment(value)))
+            .collect();
+        handler(request, params, state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{mock::MockDB, Database};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_routes() {
+        let router = new_router().unwrap();
+        assert_eq!(
+            *router.at("/api/v1/orders").unwrap().value,
+            endpoints::ORDERS
+        );
+        assert_eq!(
+            *router.at("/api/v1/orders/1").unwrap().value,
+            endpoints::ORDER_BY_ID
+        );
+        assert_eq!(
+            *router.at("/api/v1/orders/1/items").unwrap().value,
+            endpoints::ITEMS
+        );
+        assert_eq!(
+            *router.at("/api/v1/orders/1/items/2").unwrap().value,
+            endpoints::ITEM_BY_ID
+        );
+        assert_eq!(
+            *router.at("/api/v1/orders/1/bill").unwrap().value,
+            endpoints::BILL
+        );
+        assert_eq!(
+            *router.at("/api/v1/orders/1/bill/split").unwrap().value,
+            endpoints::SPLIT_BILL
+        );
+        assert_eq!(*router.at("/api/v1/menu").unwrap().value, endpoints::MENU);
+        assert_eq!(
+            *router.at("/api/v1/menu/Pizza").unwrap().value,
+            endpoints::MENU_ENTRY
+        );
+    }
+
+    #[test]
+    fn test_route_ids() {
+        let router = new_router().unwrap();
+        let route = router.at("/api/v1/orders/1/items/2").unwrap();
+        let params = route.params;
+        assert_eq!(params.get("order_id"), Some("1"));
+        assert_eq!(params.get("item_id"), Some("2"));
+    }
+
+    #[test]
+    fn test_missing_routes() {
+        let router = new_router().unwrap();
+        assert!(router.at("/api/v1/missing").is_err());
+        assert!(router.at("/api/v2/orders/1").is_err());
+    }
+
+    #[test]
+    fn test_make_params() {
+        let params = make_params!(ORDER_ID : "1", ITEM_ID : "2");
+        assert_eq!(params.get(params::ORDER_ID).unwrap(), "1");
+        assert_eq!(params.get(params::ITEM_ID).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_router() {
+        const EXPECTED_GET_ORDER: &str = "get_orders";
+        const EXPECTED_POST_ORDER: &str = "post_orders";
+        const EXPECTED_DELETE_ITEM: &str = "delete_item";
+
+        let db = MockDB::new().unwrap();
+
+        let mut router = HttpRouter::new().unwrap();
+        router.add_route(Method::Get, endpoints::ORDERS, |_, _, _| {
+            Ok(Response::ok_with_body(EXPECTED_GET_ORDER.to_string()))
+        });
+        router.add_route(Method::Post, endpoints::ORDERS, |_, _, _| {
+            Ok(Response::ok_with_body(EXPECTED_POST_ORDER.to_string()))
+        });
+        router.add_route(Method::Delete, endpoints::ITEMS, |_, _, _| {
+            Ok(Response::ok_with_body(EXPECTED_DELETE_ITEM.to_string()))
+        });
+
+        let response = router.route(Request::get(paths::ORDERS), &db).unwrap();
+        assert_eq!(response.text().unwrap(), EXPECTED_GET_ORDER);
+
+        let response = router
+            .route(Request::post(paths::ORDERS, "".to_string()), &db)
+            .unwrap();
+        assert_eq!(response.text().unwrap(), EXPECTED_POST_ORDER);
+
+        let err = router
+            .route(Request::delete(paths::ORDERS, "".to_string()), &db)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::MethodNotAllowed(allowed))
+                if allowed == &[Method::Get, Method::Head, Method::Post, Method::Options]
+        ));
+
+        let response = router
+            .route(Request::delete(paths::ITEMS, "".to_string()), &db)
+            .unwrap();
+        assert_eq!(response.text().unwrap(), EXPECTED_DELETE_ITEM);
+    }
+
+    #[test]
+    fn test_route_parameters() {
+        let mut router = HttpRouter::new().unwrap();
+        let db = MockDB::new().unwrap();
+
+        router.add_route(Method::Post, endpoints::ITEM_BY_ID, |_, params, _| {
+            let order_id = params.get("order_id").unwrap();
+            let item_id = params.get("item_id").unwrap();
+            Ok(Response::ok_with_body(format!("{}:{}", order_id, item_id)))
+        });
+
+        let response = router
+            .route(
+                Request::post("/api/v1/orders/42/items/24", "".to_string()),
+                &db,
+            )
+            .unwrap();
+
+        assert_eq!(response.text().unwrap(), "42:24");
+    }
+
+    #[test]
+    fn test_encoded_route_parameters() {
+        let mut router = HttpRouter::new().unwrap();
+        let db = MockDB::new().unwrap();
+
+        router.add_route(Method::Get, endpoints::MENU_ENTRY, |_, params, _| {
+            Ok(Response::ok_with_body(
+                params.get(params::NAME).unwrap().to_string(),
+            ))
+        });
+        router.add_route(Method::Get, endpoints::ITEM_BY_ID, |_, _, _| {
+            Ok(Response::ok_with_body("item"))
+        });
+
+        let response = router
+            .route(Request::get("/api/v1/menu/Fish%20%2F%20Chips"), &db)
+            .unwrap();
+        assert_eq!(response.text().unwrap(), "Fish / Chips");
+
+        // An encoded slash doesn't separate segments
+        let err = router
+            .route(Request::get("/api/v1/orders/1%2Fitems%2F0"), &db)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_route_with_query() {
+        #[derive(serde::Deserialize)]
+        struct Options {
+            verbose: bool,
+        }
+
+        let mut router = HttpRouter::new().unwrap();
+        let db = MockDB::new().unwrap();
+
+        router.add_route(Method::Get, endpoints::ORDER_BY_ID, |req, params, _| {
+            let options = req.query.deserialize::<Options>()?;
+            Ok(Response::ok_with_body(format!(
+                "{}:{}",
+                params.get(params::ORDER_ID).unwrap(),
+                options.verbose
+            )))
+        });
+
+        let response = router
+            .route(Request::get("/api/v1/orders/1?verbose=true"), &db)
+            .unwrap();
+        assert_eq!(response.text().unwrap(), "1:true");
+
+        let response = router.route(Request::get("/api/v1/orders/1?verbose=maybe"), &db);
+        assert!(response.is_err());
+    }
+
+    #[test]
+    fn test_automatic_head_and_options() {
+        let mut router = HttpRouter::new().unwrap();
+        let db = MockDB::new().unwrap();
+
+        router.add_route(Method::Get, endpoints::ORDERS, |_, _, _| {
+            Ok(Response::ok_with_body("orders"))
+        });
+        router.add_route(Method::Delete, endpoints::ITEM_BY_ID, |_, _, _| {
+            Ok(Response::ok())
+        });
+
+        let response = router
+            .route(
+                Request::new(Method::Head, paths::ORDERS, Default::default(), ""),
+                &db,
+            )
+            .unwrap();
+        assert_eq!(response.text().unwrap(), "orders");
+
+        let response = router
+            .route(
+                Request::new(Method::Options, paths::ORDERS, Default::default(), ""),
+                &db,
+            )
+            .unwrap();
+        assert_eq!(response.status, crate::http::StatusCode::NO_CONTENT);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        // No GET, so no HEAD either
+        let item = item_by_id(1, 2);
+        let response = router
+            .route(
+                Request::new(Method::Options, &item, Default::default(), ""),
+                &db,
+            )
+            .unwrap();
+        assert_eq!(response.headers.get("Allow"), Some("DELETE, OPTIONS"));
+        let err = router
+            .route(
+                Request::new(Method::Head, &item, Default::default(), ""),
+                &db,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::MethodNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn test_stateful_handlers() {
+        struct State {
+            calls: AtomicU32,
+        }
+
+        let mut router = HttpRouter::new().unwrap();
+        let state = State {
+            calls: Default::default(),
+        };
+
+        let greeting = "Welcome".to_string();
+        router.add_route(
+            Method::Get,
+            endpoints::ORDERS,
+            move |_, _, state: &State| {
+                let calls = state.calls.fetch_add(1, Ordering::Relaxed) + 1;
+                Ok(Response::ok_with_body(format!("{} #{}", greeting, calls)))
+            },
+        );
+
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.text().unwrap(), "Welcome #1");
+        let response = router.route(Request::get(paths::ORDERS), &state).unwrap();
+        assert_eq!(response.text().unwrap(), "Welcome #2");
+        assert_eq!(state.calls.into_inner(), 2);
+    }
+}